@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use heightmap::SharedHeightSource;
+use grass;
+use grass::{GrassBucket, GrassSettings};
+use props;
+use props::{PropInstances, PropKind};
+use rendering::TerrainVertex;
+use terrain;
+
+// Bounds how stale the worker's queue can get while the camera keeps moving
+const MAX_IN_FLIGHT: usize = 4;
+
+/// Integer grid coordinates of a chunk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkCoord {
+    /// The chunk containing a world position, for chunks `cells` grid squares wide.
+    pub fn containing(x: f32, z: f32, cells: u32) -> Self {
+        ChunkCoord {
+            x: (x / cells as f32).floor() as i32,
+            z: (z / cells as f32).floor() as i32,
+        }
+    }
+
+    /// World position of the first vertex of the chunk.
+    pub fn origin(&self, cells: u32) -> (f32, f32) {
+        ((self.x * cells as i32) as f32, (self.z * cells as i32) as f32)
+    }
+
+    /// Distance in whole chunks, along the axis where it is largest.
+    pub fn distance(&self, other: ChunkCoord) -> i32 {
+        (self.x - other.x).abs().max((self.z - other.z).abs())
+    }
+}
+
+/// Everything generated for a chunk, before it is uploaded to the GPU.
+pub struct ChunkData {
+    pub coord: ChunkCoord,
+    pub vertices: Vec<TerrainVertex>,
+    pub grass: Vec<GrassBucket>,
+    pub props: Vec<PropInstances>,
+}
+
+/// Generates chunks. Lives on the worker thread.
+pub struct ChunkBuilder {
+    heights: SharedHeightSource,
+    grass: Arc<GrassSettings>,
+    props: Arc<Vec<PropKind>>,
+    size: u32,
+    seed: u32,
+}
+
+/// Every chunk gets its own grass seed, so neighbours do not repeat each other.
+fn chunk_seed(seed: u32, coord: ChunkCoord) -> u32 {
+    seed ^ (coord.x as u32).wrapping_mul(73856093) ^ (coord.z as u32).wrapping_mul(19349663)
+}
+
+impl ChunkBuilder {
+    /// Chunks are `size` by `size` vertices, sharing their edge vertices with their neighbours.
+    pub fn new(heights: SharedHeightSource, grass: Arc<GrassSettings>, props: Arc<Vec<PropKind>>, size: u32, seed: u32) -> Self {
+        ChunkBuilder {
+            heights: heights,
+            grass: grass,
+            props: props,
+            size: size,
+            seed: seed,
+        }
+    }
+
+    pub fn build(&self, coord: ChunkCoord) -> ChunkData {
+        let (x, z) = coord.origin(self.size - 1);
+        let seed = chunk_seed(self.seed, coord);
+
+        ChunkData {
+            coord: coord,
+            vertices: terrain::build_vertices(&*self.heights, self.size, x, z),
+            grass: grass::bucket_grass(grass::scatter_grass(&*self.heights, &*self.grass, self.size, x, z, seed), &self.grass.species, self.size, x, z),
+            props: props::scatter_props(&*self.heights, &self.props, &self.grass.rules, self.size, x, z, seed),
+        }
+    }
+}
+
+/// Builds chunks around a moving position on a worker thread, so the caller never waits on them.
+pub struct ChunkStreamer {
+    requests: Sender<ChunkCoord>,
+    results: Receiver<ChunkData>,
+    pending: HashSet<ChunkCoord>,
+    cells: u32,
+    view_radius: i32,
+    // Chunks along x and z when the heights only cover a finite area, beyond it they are flat
+    limit: Option<(i32, i32)>,
+}
+
+impl ChunkStreamer {
+    /// Keeps chunks up to `view_radius` chunks away from the center loaded.
+    pub fn new(builder: ChunkBuilder, view_radius: i32) -> Self {
+        let cells = builder.size - 1;
+        let limit = builder.heights.extent().map(|(width, depth)| {
+            ((width / cells as f32).ceil() as i32, (depth / cells as f32).ceil() as i32)
+        });
+        let (request_tx, request_rx) = channel::<ChunkCoord>();
+        let (result_tx, result_rx) = channel();
+
+        // Exits once the streamer, and with it the request sender, is dropped
+        thread::spawn(move || {
+            for coord in request_rx.iter() {
+                if result_tx.send(builder.build(coord)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        ChunkStreamer {
+            requests: request_tx,
+            results: result_rx,
+            pending: HashSet::new(),
+            cells: cells,
+            view_radius: view_radius,
+            limit: limit,
+        }
+    }
+
+    /// The chunk the world position is in.
+    pub fn center(&self, x: f32, z: f32) -> ChunkCoord {
+        ChunkCoord::containing(x, z, self.cells)
+    }
+
+    pub fn view_radius(&self) -> i32 {
+        self.view_radius
+    }
+
+    /// Whether a chunk should be loaded when `center` is the camera chunk.
+    pub fn is_wanted(&self, coord: ChunkCoord, center: ChunkCoord) -> bool {
+        coord.distance(center) <= self.view_radius && self.in_limit(coord)
+    }
+
+    /// Whether the height source reaches into the chunk at all.
+    fn in_limit(&self, coord: ChunkCoord) -> bool {
+        match self.limit {
+            Some((x, z)) => coord.x >= 0 && coord.z >= 0 && coord.x < x && coord.z < z,
+            None => true,
+        }
+    }
+
+    /// Queues the missing chunks around `center`, nearest first.
+    pub fn request<F: Fn(ChunkCoord) -> bool>(&mut self, center: ChunkCoord, is_loaded: F) {
+        let mut missing = Vec::new();
+        for x in (center.x - self.view_radius)..(center.x + self.view_radius + 1) {
+            for z in (center.z - self.view_radius)..(center.z + self.view_radius + 1) {
+                let coord = ChunkCoord { x: x, z: z };
+                if self.in_limit(coord) && !is_loaded(coord) && !self.pending.contains(&coord) {
+                    missing.push(coord);
+                }
+            }
+        }
+        missing.sort_by(|a, b| a.distance(center).cmp(&b.distance(center)));
+
+        for coord in missing {
+            if self.pending.len() >= MAX_IN_FLIGHT {
+                break;
+            }
+            self.requests.send(coord).unwrap();
+            self.pending.insert(coord);
+        }
+    }
+
+    /// Chunks finished since the last call.
+    pub fn poll(&mut self) -> Vec<ChunkData> {
+        let mut finished = Vec::new();
+        loop {
+            match self.results.try_recv() {
+                Ok(data) => {
+                    self.pending.remove(&data.coord);
+                    finished.push(data);
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => panic!("Chunk worker thread died"),
+            }
+        }
+        return finished;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use heightfield::HeightField;
+    use grass::GrassSettings;
+    use super::{ChunkBuilder, ChunkCoord, ChunkStreamer};
+
+    fn streamer(heights: HeightField) -> ChunkStreamer {
+        let builder = ChunkBuilder::new(Arc::new(heights), Arc::new(GrassSettings::uniform(0.0, 1.0)), Arc::new(Vec::new()), 17, 1);
+        ChunkStreamer::new(builder, 2)
+    }
+
+    #[test]
+    fn streaming_stays_on_finite_heights() {
+        // Two chunks along x, the second only partly covered, and one along z
+        let mut streamer = streamer(HeightField::from_heights(20, 17, vec![0.0; 20 * 17]));
+        let center = ChunkCoord { x: 0, z: 0 };
+
+        streamer.request(center, |_| false);
+        let mut requested: Vec<(i32, i32)> = streamer.pending.iter().map(|coord| (coord.x, coord.z)).collect();
+        requested.sort();
+        assert_eq!(requested, vec![(0, 0), (1, 0)]);
+
+        assert!(streamer.is_wanted(ChunkCoord { x: 1, z: 0 }, center));
+        assert!(!streamer.is_wanted(ChunkCoord { x: -1, z: 0 }, center));
+        assert!(!streamer.is_wanted(ChunkCoord { x: 0, z: 1 }, center));
+    }
+}
@@ -0,0 +1,118 @@
+use glium;
+use glium::Surface;
+use glium::framebuffer::MultiOutputFrameBuffer;
+use glium::texture::{DepthFormat, DepthTexture2d, Texture2d, UncompressedFloatFormat};
+
+use lighting::LightParams;
+use rendering;
+use rendering::{RenderData, Vertex};
+use sky::Sky;
+
+/// How a G-buffer texel is lit, written by the shaders into the material target.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Material {
+    /// Already final, like the sky and the wireframe, passed through as it is.
+    Unlit = 0,
+    Terrain = 1,
+    Grass = 2,
+    Prop = 3,
+}
+
+/// Camera state the lighting pass needs to put the G-buffer texels back in the world.
+pub struct View {
+    pub persp_matrix: [[f32; 4]; 4],
+    pub view_matrix: [[f32; 4]; 4],
+    pub eye_position: [f32; 3],
+}
+
+/// Render targets the size of the window.
+struct GBuffer {
+    albedo: Texture2d,
+    normal: Texture2d,
+    depth: Texture2d,
+    material: Texture2d,
+    depth_buffer: DepthTexture2d,
+}
+
+impl GBuffer {
+    fn new<F: glium::backend::Facade>(display: &F, width: u32, height: u32) -> Self {
+        GBuffer {
+            albedo: Texture2d::new_empty(display, UncompressedFloatFormat::F32F32F32F32, width, height),
+            normal: Texture2d::new_empty(display, UncompressedFloatFormat::F32F32F32F32, width, height),
+            depth: Texture2d::new_empty(display, UncompressedFloatFormat::F32, width, height),
+            material: Texture2d::new_empty(display, UncompressedFloatFormat::F32, width, height),
+            depth_buffer: DepthTexture2d::new_empty(display, DepthFormat::F32, width, height),
+        }
+    }
+}
+
+/// The G-buffer everything is drawn into, and the pass lighting it onto the screen.
+///
+/// Shaders drawing into the G-buffer write `albedo_output`, `normal_output` (world space),
+/// `depth_output` (distance to the eye) and `material_output` (a `Material`).
+pub struct DeferredRenderer {
+    targets: GBuffer,
+    quad: RenderData<Vertex>,
+    lighting_program: glium::Program,
+}
+
+impl DeferredRenderer {
+    pub fn new<F: glium::backend::Facade>(display: &F, lighting_program: glium::Program, width: u32, height: u32) -> Self {
+        DeferredRenderer {
+            targets: GBuffer::new(display, width, height),
+            quad: rendering::fullscreen_quad(display),
+            lighting_program: lighting_program,
+        }
+    }
+
+    /// Recreates the G-buffer for a window of the new size.
+    pub fn resize<F: glium::backend::Facade>(&mut self, display: &F, width: u32, height: u32) {
+        // Minimised windows report a size of zero, keep the old targets until they come back
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.targets = GBuffer::new(display, width, height);
+    }
+
+    /// The G-buffer, cleared to unlit black and the far plane.
+    pub fn gbuffer<'a, F: glium::backend::Facade>(&'a self, display: &F) -> MultiOutputFrameBuffer<'a> {
+        let targets = &self.targets;
+        let outputs = &[
+            ("albedo_output", &targets.albedo),
+            ("normal_output", &targets.normal),
+            ("depth_output", &targets.depth),
+            ("material_output", &targets.material),
+        ];
+
+        let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(display, outputs, &targets.depth_buffer);
+        framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+        return framebuffer;
+    }
+
+    /// Lights the G-buffer with the sun and sky into `target`.
+    pub fn render<S: Surface>(&self, target: &mut S, view: &View, sky: &Sky, light: &LightParams) {
+        let (sky_light, ground_light) = light.ambient(sky.daylight());
+        let uniforms = uniform! {
+            view_rays: rendering::view_ray_matrix(view.persp_matrix, view.view_matrix),
+            eye_position: view.eye_position,
+
+            albedo_texture: &self.targets.albedo,
+            normal_texture: &self.targets.normal,
+            depth_texture: &self.targets.depth,
+            material_texture: &self.targets.material,
+
+            sun_direction: sky.sun_direction(),
+            sun_color: sky.sun_color(),
+            sun_intensity: light.sun_intensity,
+            sky_light: sky_light,
+            ground_light: ground_light,
+            translucency: light.translucency,
+            translucency_power: light.translucency_power,
+
+            material_unlit: Material::Unlit as i32,
+            material_grass: Material::Grass as i32,
+        };
+
+        target.draw(self.quad.get_vb(), self.quad.get_ib(), &self.lighting_program, &uniforms, &::std::default::Default::default()).unwrap();
+    }
+}
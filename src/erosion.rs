@@ -0,0 +1,294 @@
+extern crate rand;
+
+use rand::Rng;
+
+use heightfield::HeightField;
+use heightmap;
+
+// Keeps the droplet stream independent from the other users of the seed
+const EROSION_SEED_SALT: u32 = 0x65726f64;
+
+/// Material slumping down slopes steeper than the talus angle.
+#[derive(Copy, Clone, Debug)]
+pub struct ThermalParams {
+    pub iterations: u32,
+    /// Largest height difference between two neighbouring samples that stays put.
+    pub talus: f32,
+    /// Fraction of the excess height moved each iteration, at most 0.5 to stay stable.
+    pub strength: f32,
+}
+
+impl Default for ThermalParams {
+    fn default() -> Self {
+        ThermalParams {
+            iterations: 50,
+            talus: 0.6,
+            strength: 0.25,
+        }
+    }
+}
+
+/// Rain droplets running downhill, picking up and dropping sediment as they go.
+#[derive(Copy, Clone, Debug)]
+pub struct HydraulicParams {
+    /// Number of droplets simulated, one after the other.
+    pub iterations: u32,
+    /// Steps a droplet lives for, unless it runs off the grid first.
+    pub max_lifetime: u32,
+    /// How much a droplet keeps its direction instead of following the slope, 0..1.
+    pub inertia: f32,
+    /// Water carried by a new droplet.
+    pub rain: f32,
+    /// Fraction of the water evaporating each step.
+    pub evaporation: f32,
+    /// Sediment carried per unit of water, speed and slope.
+    pub sediment_capacity: f32,
+    /// Slope used for the capacity on flat ground, so droplets keep eroding a little.
+    pub min_slope: f32,
+    /// Fraction of the free capacity eroded each step.
+    pub erosion_rate: f32,
+    /// Fraction of the surplus sediment deposited each step.
+    pub deposition_rate: f32,
+    pub gravity: f32,
+}
+
+impl Default for HydraulicParams {
+    fn default() -> Self {
+        HydraulicParams {
+            iterations: 20000,
+            max_lifetime: 30,
+            inertia: 0.05,
+            rain: 1.0,
+            evaporation: 0.01,
+            sediment_capacity: 4.0,
+            min_slope: 0.01,
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            gravity: 4.0,
+        }
+    }
+}
+
+/// Moves material from every sample to its lower 4-neighbours while the drop
+/// between them is larger than `params.talus`. The total volume is preserved.
+pub fn thermal(field: &mut HeightField, params: &ThermalParams) {
+    let (width, depth) = (field.width(), field.depth());
+    let offsets: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+    for _ in 0..params.iterations {
+        let mut delta = vec![0.0; width * depth];
+
+        for z in 0..depth {
+            for x in 0..width {
+                let h = field.get(x, z);
+
+                let mut neighbours = [(0, 0, 0.0); 4];
+                let mut count = 0;
+                let mut total_excess = 0.0;
+                let mut max_excess: f32 = 0.0;
+
+                for &(ox, oz) in offsets.iter() {
+                    let nx = x as isize + ox;
+                    let nz = z as isize + oz;
+                    if nx < 0 || nz < 0 || nx >= width as isize || nz >= depth as isize {
+                        continue;
+                    }
+
+                    let (nx, nz) = (nx as usize, nz as usize);
+                    let excess = h - field.get(nx, nz) - params.talus;
+                    if excess > 0.0 {
+                        neighbours[count] = (nx, nz, excess);
+                        count += 1;
+                        total_excess += excess;
+                        max_excess = max_excess.max(excess);
+                    }
+                }
+
+                // Steeper drops get a larger share of what is moved
+                let moved = params.strength * max_excess;
+                for &(nx, nz, excess) in neighbours[..count].iter() {
+                    let share = moved * excess / total_excess;
+                    delta[nz * width + nx] += share;
+                    delta[z * width + x] -= share;
+                }
+            }
+        }
+
+        for z in 0..depth {
+            for x in 0..width {
+                let h = field.get(x, z);
+                field.set(x, z, h + delta[z * width + x]);
+            }
+        }
+    }
+}
+
+/// Height and gradient of the bilinear surface through the cell containing (x, z).
+fn height_and_gradient(field: &HeightField, x: f32, z: f32) -> (f32, f32, f32) {
+    let (cx, cz) = (x.floor() as usize, z.floor() as usize);
+    let (fx, fz) = (x - cx as f32, z - cz as f32);
+
+    let h00 = field.get(cx, cz);
+    let h10 = field.get(cx + 1, cz);
+    let h01 = field.get(cx, cz + 1);
+    let h11 = field.get(cx + 1, cz + 1);
+
+    let gx = (h10 - h00) * (1.0 - fz) + (h11 - h01) * fz;
+    let gz = (h01 - h00) * (1.0 - fx) + (h11 - h10) * fx;
+    let h = h00 * (1.0 - fx) * (1.0 - fz) + h10 * fx * (1.0 - fz) + h01 * (1.0 - fx) * fz + h11 * fx * fz;
+
+    return (h, gx, gz);
+}
+
+/// Spreads `amount` over the four corners of the cell containing (x, z).
+fn add_bilinear(field: &mut HeightField, x: f32, z: f32, amount: f32) {
+    let (cx, cz) = (x.floor() as usize, z.floor() as usize);
+    let (fx, fz) = (x - cx as f32, z - cz as f32);
+
+    let corners = [
+        (cx, cz, (1.0 - fx) * (1.0 - fz)),
+        (cx + 1, cz, fx * (1.0 - fz)),
+        (cx, cz + 1, (1.0 - fx) * fz),
+        (cx + 1, cz + 1, fx * fz),
+    ];
+
+    for &(px, pz, weight) in corners.iter() {
+        let h = field.get(px, pz);
+        field.set(px, pz, h + amount * weight);
+    }
+}
+
+/// Simulates `params.iterations` droplets, deterministic for a given seed.
+pub fn hydraulic(field: &mut HeightField, params: &HydraulicParams, seed: u32) {
+    if field.width() < 2 || field.depth() < 2 {
+        return;
+    }
+
+    let max_x = (field.width() - 1) as f32;
+    let max_z = (field.depth() - 1) as f32;
+    let mut rng = heightmap::seeded_rng(seed ^ EROSION_SEED_SALT);
+
+    for _ in 0..params.iterations {
+        let mut x = rng.gen::<f32>() * max_x;
+        let mut z = rng.gen::<f32>() * max_z;
+        let (mut dir_x, mut dir_z) = (0.0, 0.0);
+        let mut speed = 1.0;
+        let mut water = params.rain;
+        let mut sediment = 0.0;
+
+        for _ in 0..params.max_lifetime {
+            let (h, gx, gz) = height_and_gradient(field, x, z);
+
+            dir_x = dir_x * params.inertia - gx * (1.0 - params.inertia);
+            dir_z = dir_z * params.inertia - gz * (1.0 - params.inertia);
+            let length = (dir_x * dir_x + dir_z * dir_z).sqrt();
+            if length == 0.0 {
+                break;
+            }
+            dir_x /= length;
+            dir_z /= length;
+
+            let (new_x, new_z) = (x + dir_x, z + dir_z);
+            if new_x < 0.0 || new_z < 0.0 || new_x >= max_x || new_z >= max_z {
+                break;
+            }
+
+            let dh = height_and_gradient(field, new_x, new_z).0 - h;
+            let capacity = (-dh).max(params.min_slope) * speed * water * params.sediment_capacity;
+
+            if sediment > capacity || dh > 0.0 {
+                // Going uphill fills the pit behind the droplet, otherwise drop the surplus
+                let deposit = if dh > 0.0 {
+                    dh.min(sediment)
+                } else {
+                    (sediment - capacity) * params.deposition_rate
+                };
+                sediment -= deposit;
+                add_bilinear(field, x, z, deposit);
+            } else {
+                // Never dig deeper than the drop to the next position
+                let eroded = ((capacity - sediment) * params.erosion_rate).min(-dh);
+                sediment += eroded;
+                add_bilinear(field, x, z, -eroded);
+            }
+
+            speed = (speed * speed - dh * params.gravity).max(0.0).sqrt();
+            water *= 1.0 - params.evaporation;
+            x = new_x;
+            z = new_z;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heightfield::HeightField;
+    use super::{hydraulic, thermal, HydraulicParams, ThermalParams};
+
+    /// Bumpy ground with a spike in the middle.
+    fn rough_field(width: usize, depth: usize) -> HeightField {
+        let mut heights: Vec<f32> = (0..width * depth).map(|i| ((i % width * 7 + i / width * 13) % 5) as f32 * 0.8).collect();
+        heights[depth / 2 * width + width / 2] = 10.0;
+        HeightField::from_heights(width, depth, heights)
+    }
+
+    fn volume(field: &HeightField) -> f32 {
+        field.heights().iter().fold(0.0, |sum, &h| sum + h)
+    }
+
+    /// Largest height difference between two 4-neighbours.
+    fn max_drop(field: &HeightField) -> f32 {
+        let mut drop: f32 = 0.0;
+        for z in 0..field.depth() {
+            for x in 0..field.width() {
+                if x + 1 < field.width() {
+                    drop = drop.max((field.get(x, z) - field.get(x + 1, z)).abs());
+                }
+                if z + 1 < field.depth() {
+                    drop = drop.max((field.get(x, z) - field.get(x, z + 1)).abs());
+                }
+            }
+        }
+        return drop;
+    }
+
+    #[test]
+    fn thermal_keeps_volume_and_flattens_to_talus() {
+        let mut field = rough_field(9, 9);
+        let before = volume(&field);
+        let params = ThermalParams { iterations: 500, .. ThermalParams::default() };
+        assert!(max_drop(&field) > params.talus);
+
+        thermal(&mut field, &params);
+
+        assert!((volume(&field) - before).abs() < 1e-3 * before);
+        assert!(max_drop(&field) < params.talus + 1e-3);
+    }
+
+    #[test]
+    fn hydraulic_follows_the_seed() {
+        let params = HydraulicParams { iterations: 500, .. HydraulicParams::default() };
+        let eroded = |seed| {
+            let mut field = rough_field(16, 12);
+            hydraulic(&mut field, &params, seed);
+            field.heights().to_vec()
+        };
+
+        assert_eq!(eroded(3), eroded(3));
+        assert!(eroded(3) != eroded(4));
+        assert!(eroded(3) != rough_field(16, 12).heights().to_vec());
+    }
+
+    #[test]
+    fn tiny_fields_erode_without_panicking() {
+        let thermal_params = ThermalParams::default();
+        let hydraulic_params = HydraulicParams { iterations: 200, .. HydraulicParams::default() };
+
+        for &(width, depth) in [(1, 1), (1, 7), (7, 1), (2, 2)].iter() {
+            let mut field = rough_field(width, depth);
+            thermal(&mut field, &thermal_params);
+            hydraulic(&mut field, &hydraulic_params, 5);
+            assert!(field.heights().iter().all(|h| h.is_finite()));
+        }
+    }
+}
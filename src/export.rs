@@ -0,0 +1,98 @@
+extern crate image;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use self::image::ColorType;
+
+use heightfield::HeightField;
+use heightmap::HeightSource;
+use grass;
+use grass::GrassSettings;
+use rendering::{GrassAttrs, TerrainVertex};
+use terrain;
+
+/// Writes the heights as a 16-bit grayscale PNG, stretched to use the full range.
+/// The real lowest and highest heights go into a `.range` file next to it,
+/// which `HeightField::load_heightmap` uses to restore them.
+pub fn write_heightfield_png(path: &Path, field: &HeightField) -> io::Result<()> {
+    let heights = field.heights();
+    let min = heights.iter().fold(::std::f32::INFINITY, |a, &b| a.min(b));
+    let max = heights.iter().fold(::std::f32::NEG_INFINITY, |a, &b| a.max(b));
+    let range = if max > min { max - min } else { 1.0 };
+
+    // PNG stores 16-bit samples big-endian
+    let mut bytes = Vec::with_capacity(heights.len() * 2);
+    for &h in heights {
+        let value = ((h - min) / range * 65535.0).round() as u16;
+        bytes.push((value >> 8) as u8);
+        bytes.push(value as u8);
+    }
+
+    let mut file = try!(File::create(path));
+    try!(image::png::PNGEncoder::new(&mut file).encode(&bytes, field.width() as u32, field.depth() as u32, ColorType::Gray(16)));
+
+    let mut range_file = try!(File::create(path.with_extension("range")));
+    writeln!(range_file, "{} {}", min, min + range)
+}
+
+/// Writes a Wavefront OBJ with positions, texture coordinates and normals.
+pub fn write_obj(path: &Path, vertices: &[TerrainVertex], indices: &[u32]) -> io::Result<()> {
+    let mut out = BufWriter::new(try!(File::create(path)));
+
+    try!(writeln!(out, "# Meadow terrain"));
+    for v in vertices {
+        try!(writeln!(out, "v {} {} {}", v.position[0], v.position[1], v.position[2]));
+    }
+    for v in vertices {
+        try!(writeln!(out, "vt {} {}", v.tex_coords[0], v.tex_coords[1]));
+    }
+    for v in vertices {
+        try!(writeln!(out, "vn {} {} {}", v.normal[0], v.normal[1], v.normal[2]));
+    }
+
+    // OBJ indices start at one, and all three attributes share the same index
+    for triangle in indices.chunks(3) {
+        let (a, b, c) = (triangle[0] + 1, triangle[1] + 1, triangle[2] + 1);
+        try!(writeln!(out, "f {}/{}/{} {}/{}/{} {}/{}/{}", a, a, a, b, b, b, c, c, c));
+    }
+
+    Ok(())
+}
+
+/// Writes grass blade positions as an ASCII PLY point cloud.
+pub fn write_grass_ply(path: &Path, grass: &[GrassAttrs]) -> io::Result<()> {
+    let mut out = BufWriter::new(try!(File::create(path)));
+
+    try!(writeln!(out, "ply"));
+    try!(writeln!(out, "format ascii 1.0"));
+    try!(writeln!(out, "element vertex {}", grass.len()));
+    try!(writeln!(out, "property float x"));
+    try!(writeln!(out, "property float y"));
+    try!(writeln!(out, "property float z"));
+    try!(writeln!(out, "property float rand_factor"));
+    try!(writeln!(out, "property float species"));
+    try!(writeln!(out, "end_header"));
+
+    for blade in grass {
+        try!(writeln!(out, "{} {} {} {} {}", blade.offset[0], blade.offset[1], blade.offset[2], blade.rand_factor, blade.species));
+    }
+
+    Ok(())
+}
+
+/// Writes `heights.png` along with `heights.range`, `terrain.obj` and `grass.ply` for the `size` by `size`
+/// patch of the meadow starting at the origin into `dir`.
+pub fn export_meadow(dir: &Path, heights: &HeightSource, grass_settings: &GrassSettings, size: u32, seed: u32) -> io::Result<()> {
+    try!(fs::create_dir_all(dir));
+
+    let field = HeightField::from_source(heights, size as usize, size as usize);
+    try!(write_heightfield_png(&dir.join("heights.png"), &field));
+    try!(write_obj(&dir.join("terrain.obj"), &terrain::build_vertices(heights, size, 0.0, 0.0), &terrain::build_indices(size, terrain::max_level(size))));
+    try!(write_grass_ply(&dir.join("grass.ply"), &grass::scatter_grass(heights, grass_settings, size, 0.0, 0.0, seed)));
+
+    Ok(())
+}
@@ -0,0 +1,466 @@
+extern crate rand;
+
+use rand::Rng;
+
+use std::str::FromStr;
+
+use heightfield::HeightField;
+use heightmap;
+use heightmap::{HeightSource, NoiseContext};
+use rendering::GrassAttrs;
+use poisson;
+
+// Keeps the grass placement stream independent from the noise gradients
+const GRASS_SEED_SALT: u32 = 0x67726173;
+const DENSITY_SEED_SALT: u32 = 0x64656e73;
+
+/// How densely grass grows at a point, from 0 for bare soil to 1 for blades packed as
+/// tightly as the minimum spacing allows.
+pub trait GrassDensity {
+    fn density(&self, heights: &HeightSource, x: f32, z: f32) -> f32;
+}
+
+/// The same density everywhere.
+pub struct UniformDensity(pub f32);
+
+impl GrassDensity for UniformDensity {
+    fn density(&self, _: &HeightSource, _: f32, _: f32) -> f32 {
+        self.0
+    }
+}
+
+/// Patches of grass from low frequency noise, thinning out on steep slopes.
+pub struct NoiseDensity {
+    noise: NoiseContext,
+    /// Patches per world unit.
+    pub frequency: f32,
+    /// Rough fraction of the ground covered, 0..1.
+    pub coverage: f32,
+    /// Slope, as height over distance, at which no grass grows at all.
+    pub max_slope: f32,
+}
+
+impl NoiseDensity {
+    pub fn new(seed: u32) -> Self {
+        NoiseDensity {
+            noise: NoiseContext::with_seed(64, seed ^ DENSITY_SEED_SALT),
+            frequency: 0.05,
+            coverage: 0.7,
+            max_slope: 2.0,
+        }
+    }
+}
+
+impl GrassDensity for NoiseDensity {
+    fn density(&self, heights: &HeightSource, x: f32, z: f32) -> f32 {
+        // Noise is roughly within -0.7..0.7, fade over a short band around the coverage threshold
+        let n = 0.5 + self.noise.get(x * self.frequency, z * self.frequency);
+        let patch = ((n - (1.0 - self.coverage)) * 4.0 + 0.5).max(0.0).min(1.0);
+
+        let (_, dx, dz) = heights.get_height_with_derivative(x, z);
+        let slope = (dx * dx + dz * dz).sqrt();
+        let flatness = (1.0 - slope / self.max_slope).max(0.0);
+
+        return patch * flatness;
+    }
+}
+
+/// Density painted into a grayscale image, white for full grass.
+pub struct MaskDensity {
+    mask: HeightField,
+    /// World units covered by one pixel.
+    scale: f32,
+}
+
+impl MaskDensity {
+    /// The mask is loaded like a height field, so it is normalised to 0..1.
+    pub fn new(mask: HeightField, scale: f32) -> Self {
+        MaskDensity { mask: mask, scale: scale }
+    }
+}
+
+impl GrassDensity for MaskDensity {
+    fn density(&self, _: &HeightSource, x: f32, z: f32) -> f32 {
+        self.mask.sample(x / self.scale, z / self.scale)
+    }
+}
+
+/// A region where no grass grows, like a path or the foundation of a house.
+#[derive(Copy, Clone, Debug)]
+pub enum Exclusion {
+    Circle { x: f32, z: f32, radius: f32 },
+    Rect { min_x: f32, min_z: f32, max_x: f32, max_z: f32 },
+}
+
+impl Exclusion {
+    pub fn contains(&self, px: f32, pz: f32) -> bool {
+        match *self {
+            Exclusion::Circle { x, z, radius } => (px - x) * (px - x) + (pz - z) * (pz - z) <= radius * radius,
+            Exclusion::Rect { min_x, min_z, max_x, max_z } => px >= min_x && px <= max_x && pz >= min_z && pz <= max_z,
+        }
+    }
+}
+
+/// Parses `circle:x,z,radius` or `rect:min_x,min_z,max_x,max_z`.
+impl FromStr for Exclusion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let values: Vec<f32> = match parts.next() {
+            Some(values) => try!(values.split(',').map(|v| v.trim().parse().map_err(|_| ())).collect()),
+            None => return Err(()),
+        };
+
+        match (kind, values.len()) {
+            ("circle", 3) => Ok(Exclusion::Circle { x: values[0], z: values[1], radius: values[2] }),
+            ("rect", 4) => Ok(Exclusion::Rect { min_x: values[0], min_z: values[1], max_x: values[2], max_z: values[3] }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Hard limits on where a blade may be placed, checked for every single blade.
+/// Unlike the density these never let a blade through on disallowed ground.
+#[derive(Clone, Debug)]
+pub struct PlacementRules {
+    /// Steepest slope, as height over distance, grass grows on.
+    pub max_slope: Option<f32>,
+    pub min_altitude: Option<f32>,
+    pub max_altitude: Option<f32>,
+    pub exclusions: Vec<Exclusion>,
+}
+
+impl Default for PlacementRules {
+    fn default() -> Self {
+        PlacementRules {
+            max_slope: None,
+            min_altitude: None,
+            max_altitude: None,
+            exclusions: Vec::new(),
+        }
+    }
+}
+
+impl PlacementRules {
+    /// Whether a blade may grow at (x, z), where the ground is at `height`.
+    pub fn allows(&self, heights: &HeightSource, x: f32, z: f32, height: f32) -> bool {
+        if self.exclusions.iter().any(|exclusion| exclusion.contains(x, z)) {
+            return false;
+        }
+
+        if self.min_altitude.map_or(false, |min| height < min) || self.max_altitude.map_or(false, |max| height > max) {
+            return false;
+        }
+
+        // Only pay for the derivative when there is a slope limit
+        if let Some(max_slope) = self.max_slope {
+            let (_, dx, dz) = heights.get_height_with_derivative(x, z);
+            if (dx * dx + dz * dz).sqrt() > max_slope {
+                return false;
+            }
+        }
+
+        return true;
+    }
+}
+
+/// Most segments a blade can have, limited by `max_vertices` in `grass.gs`.
+pub const MAX_BLADE_SEGMENTS: i32 = 7;
+
+/// How blades simplify with their distance to the camera.
+#[derive(Copy, Clone, Debug)]
+pub struct GrassLod {
+    /// Segments of the blades right in front of the camera.
+    pub max_segments: i32,
+    /// Segments of the blades at `segment_distance` and beyond.
+    pub min_segments: i32,
+    pub segment_distance: f32,
+    /// Distance beyond which blades are drawn as a single textured card.
+    pub card_distance: f32,
+    /// Distance where blades start thinning out.
+    pub thin_start: f32,
+    /// Distance beyond which no blades are drawn at all.
+    pub max_distance: f32,
+}
+
+impl Default for GrassLod {
+    fn default() -> Self {
+        GrassLod {
+            max_segments: 5,
+            min_segments: 1,
+            segment_distance: 30.0,
+            card_distance: 35.0,
+            thin_start: 30.0,
+            max_distance: 60.0,
+        }
+    }
+}
+
+/// The look of one kind of plant, read by `grass.gs` and `grass.fs`.
+#[derive(Clone, Debug)]
+pub struct Species {
+    pub name: String,
+    /// Blade heights are spread evenly between these.
+    pub min_height: f32,
+    pub max_height: f32,
+    pub width: f32,
+    /// Multiplied with the texture colour.
+    pub tint: [f32; 3],
+    /// Layer of the grass texture array. Only the grass texture ships, so every
+    /// built in species uses layer 0.
+    pub texture: u32,
+    /// Share of the blades, relative to the other species.
+    pub density: f32,
+    /// How much the blade resists the wind, from 0 for fully bending to 1 for rigid.
+    pub stiffness: f32,
+}
+
+/// Tall grass, clover and wheat. They share the grass texture and only differ
+/// in shape, tint and stiffness.
+pub fn default_species() -> Vec<Species> {
+    vec![
+        Species {
+            name: "tall grass".to_string(),
+            min_height: 0.8,
+            max_height: 1.2,
+            width: 0.02,
+            tint: [1.0, 1.0, 1.0],
+            texture: 0,
+            density: 0.7,
+            stiffness: 0.0,
+        },
+        Species {
+            name: "clover".to_string(),
+            min_height: 0.15,
+            max_height: 0.3,
+            width: 0.06,
+            tint: [0.7, 1.0, 0.6],
+            texture: 0,
+            density: 0.2,
+            stiffness: 0.6,
+        },
+        Species {
+            name: "wheat".to_string(),
+            min_height: 1.3,
+            max_height: 1.6,
+            width: 0.015,
+            tint: [1.4, 1.2, 0.5],
+            texture: 0,
+            density: 0.1,
+            stiffness: 0.3,
+        },
+    ]
+}
+
+/// Rows of the species data texture, one row per species:
+/// (min height, max height, width, stiffness) followed by (tint, texture layer).
+pub fn species_texels(species: &[Species]) -> Vec<Vec<(f32, f32, f32, f32)>> {
+    species.iter().map(|s| vec![
+        (s.min_height, s.max_height, s.width, s.stiffness),
+        (s.tint[0], s.tint[1], s.tint[2], s.texture as f32),
+    ]).collect()
+}
+
+/// Picks a species for `choice` in 0..1, weighted by the species densities.
+fn pick_species(species: &[Species], choice: f32) -> usize {
+    let total: f32 = species.iter().map(|s| s.density.max(0.0)).sum();
+    let mut remaining = choice * total;
+    for (i, s) in species.iter().enumerate() {
+        remaining -= s.density.max(0.0);
+        if remaining < 0.0 {
+            return i;
+        }
+    }
+    return species.len() - 1;
+}
+
+/// Everything deciding where grass grows. Shared with the chunk worker thread.
+pub struct GrassSettings {
+    pub density: Box<GrassDensity + Send + Sync>,
+    pub rules: PlacementRules,
+    /// Must not be empty, blades index into it.
+    pub species: Vec<Species>,
+    /// Smallest distance between two blades.
+    pub min_spacing: f32,
+}
+
+#[cfg(test)]
+impl GrassSettings {
+    /// The default species at the same density everywhere, without any placement rules.
+    pub fn uniform(density: f32, min_spacing: f32) -> Self {
+        GrassSettings {
+            density: Box::new(UniformDensity(density)),
+            rules: PlacementRules::default(),
+            species: default_species(),
+            min_spacing: min_spacing,
+        }
+    }
+}
+
+// Grid squares along each side of a grass bucket
+const BUCKET_CELLS: u32 = 16;
+// How far trampling pushes a blade tip sideways, in blade heights, as in grass.gs
+const TRAMPLE_BEND: f32 = 1.5;
+// Generous bound on how far the wind blows a blade tip sideways
+const WIND_REACH: f32 = 1.0;
+// Half width of the distant blade cards, in blade widths, as in grass.gs
+const CARD_WIDTH: f32 = 10.0;
+
+/// Blades close to each other, drawn or culled together.
+pub struct GrassBucket {
+    pub blades: Vec<GrassAttrs>,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// Sorts the blades of a grid starting at (`origin_x`, `origin_z`) into square buckets,
+/// with bounding boxes that include the blades of their `species` however far they bend.
+pub fn bucket_grass(grass: Vec<GrassAttrs>, species: &[Species], size: u32, origin_x: f32, origin_z: f32) -> Vec<GrassBucket> {
+    let per_side = ((size - 1 + BUCKET_CELLS - 1) / BUCKET_CELLS) as usize;
+    let mut buckets: Vec<Vec<GrassAttrs>> = (0..per_side * per_side).map(|_| Vec::new()).collect();
+
+    for blade in grass {
+        let bx = (((blade.offset[0] - origin_x) / BUCKET_CELLS as f32) as usize).min(per_side - 1);
+        let bz = (((blade.offset[2] - origin_z) / BUCKET_CELLS as f32) as usize).min(per_side - 1);
+        buckets[bz * per_side + bx].push(blade);
+    }
+
+    buckets.into_iter().filter(|blades| !blades.is_empty()).map(|blades| {
+        let mut min = blades[0].offset;
+        let mut max = blades[0].offset;
+        for blade in blades.iter() {
+            // Blades grow upwards and sway, get trampled or widen into cards sideways
+            let kind = &species[blade.species as usize];
+            let side = kind.max_height * TRAMPLE_BEND + WIND_REACH + kind.width * CARD_WIDTH;
+            let reach = [side, kind.max_height, side];
+
+            for i in 0..3 {
+                min[i] = min[i].min(blade.offset[i] - reach[i]);
+                max[i] = max[i].max(blade.offset[i] + reach[i]);
+            }
+        }
+
+        GrassBucket { blades: blades, min: min, max: max }
+    }).collect()
+}
+
+/// Grass blade positions over the grid squares of a grid of `size` by `size` vertices
+/// starting at (`origin_x`, `origin_z`), deterministic for a given seed.
+///
+/// Blades are spread as Poisson-disk blue noise and then thinned out by the density,
+/// so they keep their minimum spacing without repeating any pattern.
+pub fn scatter_grass(heights: &HeightSource, settings: &GrassSettings, size: u32, origin_x: f32, origin_z: f32, seed: u32) -> Vec<GrassAttrs> {
+    let mut attrs = Vec::new();
+    let mut rng = heightmap::seeded_rng(seed ^ GRASS_SEED_SALT);
+
+    // Density only varies slowly, so one lookup per grid square will do
+    let cells = (size - 1) as usize;
+    let mut densities = Vec::with_capacity(cells * cells);
+    for z in 0..cells {
+        for x in 0..cells {
+            let density = settings.density.density(heights, origin_x + x as f32 + 0.5, origin_z + z as f32 + 0.5);
+            densities.push(density.max(0.0).min(1.0));
+        }
+    }
+
+    // Grids are sampled on their own, so stay half the spacing clear of the edges
+    // to keep the spacing towards the neighbouring grids as well
+    let extent = cells as f32;
+    let margin = settings.min_spacing / 2.0;
+
+    for (x, z) in poisson::sample(&mut rng, extent, extent, settings.min_spacing) {
+        // Always draw all of them, so the rest of the stream does not depend on the density
+        let keep = rng.gen::<f32>();
+        let personal = rng.gen::<f32>();
+        let choice = rng.gen::<f32>();
+
+        if x < margin || z < margin || x > extent - margin || z > extent - margin {
+            continue;
+        }
+        if keep >= densities[(z as usize) * cells + x as usize] {
+            continue;
+        }
+
+        let gx = origin_x + x;
+        let gz = origin_z + z;
+        let grass_height_value = heights.get_height(gx, gz);
+        if !settings.rules.allows(heights, gx, gz, grass_height_value) {
+            continue;
+        }
+
+        let species = pick_species(&settings.species, choice);
+        attrs.push(GrassAttrs {
+            offset: [ gx, grass_height_value, gz ],
+            rand_factor: personal,
+            species: species as f32,
+            ground_normal: heights.get_normal(gx, gz),
+        });
+    }
+
+    return attrs;
+}
+
+#[cfg(test)]
+mod tests {
+    use heightfield::HeightField;
+    use heightmap::HeightSource;
+    use super::{scatter_grass, Exclusion, GrassSettings, PlacementRules};
+
+    #[test]
+    fn scattered_blades_follow_the_rules() {
+        // A valley steepening towards +x, with a bump along z
+        let size = 33;
+        let heights: Vec<f32> = (0..size * size).map(|i| {
+            let (x, z) = ((i % size) as f32, (i / size) as f32);
+            x * x / 40.0 + (z * 0.3).sin()
+        }).collect();
+        let field = HeightField::from_heights(size, size, heights);
+
+        let exclusions = vec![
+            Exclusion::Circle { x: 10.0, z: 10.0, radius: 4.0 },
+            Exclusion::Rect { min_x: 3.0, min_z: 20.0, max_x: 9.0, max_z: 28.0 },
+        ];
+        let rules = PlacementRules {
+            max_slope: Some(1.0),
+            min_altitude: Some(0.5),
+            max_altitude: Some(12.0),
+            exclusions: exclusions.clone(),
+        };
+        let settings = GrassSettings { rules: rules, .. GrassSettings::uniform(1.0, 0.3) };
+
+        let blades = scatter_grass(&field, &settings, size as u32, 0.0, 0.0, 11);
+        assert!(!blades.is_empty());
+
+        for blade in blades.iter() {
+            let (x, height, z) = (blade.offset[0], blade.offset[1], blade.offset[2]);
+            assert!(exclusions.iter().all(|exclusion| !exclusion.contains(x, z)));
+            assert!(height >= 0.5 && height <= 12.0);
+
+            let (_, dx, dz) = field.get_height_with_derivative(x, z);
+            assert!((dx * dx + dz * dz).sqrt() <= 1.0);
+        }
+
+        // Without the rules the same stream places more blades
+        let unruled = GrassSettings { rules: PlacementRules::default(), .. settings };
+        assert!(scatter_grass(&field, &unruled, size as u32, 0.0, 0.0, 11).len() > blades.len());
+    }
+
+    #[test]
+    fn exclusions_parse() {
+        match "circle:1,2.5,3".parse::<Exclusion>() {
+            Ok(Exclusion::Circle { x, z, radius }) => assert_eq!((x, z, radius), (1.0, 2.5, 3.0)),
+            other => panic!("{:?}", other),
+        }
+
+        match "rect: -4, 0.5 ,6,7".parse::<Exclusion>() {
+            Ok(Exclusion::Rect { min_x, min_z, max_x, max_z }) => assert_eq!((min_x, min_z, max_x, max_z), (-4.0, 0.5, 6.0, 7.0)),
+            other => panic!("{:?}", other),
+        }
+
+        for bad in ["", "circle", "circle:", "circle:1,2", "circle:1,2,3,4", "rect:1,2,3", "square:1,2,3", "circle:a,b,c", "1,2,3"].iter() {
+            assert!(bad.parse::<Exclusion>().is_err(), "{} parsed", bad);
+        }
+    }
+}
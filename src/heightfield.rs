@@ -0,0 +1,265 @@
+extern crate image;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use self::image::{ColorType, DecodingResult, ImageDecoder, ImageError};
+
+use heightmap::HeightSource;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Image(ImageError),
+    Format(String),
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<ImageError> for LoadError {
+    fn from(err: ImageError) -> Self {
+        LoadError::Image(err)
+    }
+}
+
+/// Sample layout of headerless height files.
+#[derive(Copy, Clone, Debug)]
+pub enum RawFormat {
+    /// Little-endian unsigned 16-bit samples, normalised to 0..1.
+    R16,
+    /// Little-endian 32-bit floats, used as they are.
+    R32,
+}
+
+/// A rectangular grid of heights, one sample per world unit.
+pub struct HeightField {
+    width: usize,
+    depth: usize,
+    heights: Vec<f32>,
+}
+
+impl HeightField {
+    /// `heights` are stored row by row, `x` varying fastest.
+    pub fn from_heights(width: usize, depth: usize, heights: Vec<f32>) -> Self {
+        assert!(width > 0 && depth > 0, "Height field must not be empty");
+        assert_eq!(heights.len(), width * depth);
+
+        HeightField {
+            width: width,
+            depth: depth,
+            heights: heights,
+        }
+    }
+
+    /// Bakes `source` into a grid, sampling it at integer coordinates.
+    pub fn from_source(source: &HeightSource, width: usize, depth: usize) -> Self {
+        let mut heights = Vec::with_capacity(width * depth);
+        for z in 0..depth {
+            for x in 0..width {
+                heights.push(source.get_height(x as f32, z as f32));
+            }
+        }
+
+        return HeightField::from_heights(width, depth, heights);
+    }
+
+    /// Loads a grayscale PNG (8 or 16 bits) or a square `.r16`/`.r32` raw file,
+    /// picked by the file extension. Heights are multiplied by `vertical_scale`.
+    pub fn load(path: &Path, vertical_scale: f32) -> Result<Self, LoadError> {
+        let mut field = try!(HeightField::load_normalised(path));
+        for h in field.heights.iter_mut() {
+            *h *= vertical_scale;
+        }
+
+        return Ok(field);
+    }
+
+    /// Loads a heightmap like `load`, except that when a `.range` file next to it,
+    /// as written by the exporter, holds the lowest and highest height, the samples
+    /// are mapped onto those instead of being scaled.
+    pub fn load_heightmap(path: &Path, vertical_scale: f32) -> Result<Self, LoadError> {
+        let (min, max) = match try!(HeightField::load_range(&path.with_extension("range"))) {
+            Some(range) => range,
+            None => return HeightField::load(path, vertical_scale),
+        };
+
+        let mut field = try!(HeightField::load_normalised(path));
+        for h in field.heights.iter_mut() {
+            *h = min + *h * (max - min);
+        }
+
+        return Ok(field);
+    }
+
+    fn load_normalised(path: &Path) -> Result<Self, LoadError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => HeightField::load_png(path),
+            Some("r16") => HeightField::load_raw(path, RawFormat::R16),
+            Some("r32") => HeightField::load_raw(path, RawFormat::R32),
+            _ => Err(LoadError::Format(format!("Unknown height file type: {}", path.display()))),
+        }
+    }
+
+    /// Reads `min max` from a range file, if there is one.
+    fn load_range(path: &Path) -> Result<Option<(f32, f32)>, LoadError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut text = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut text));
+
+        let values: Vec<f32> = text.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+        if values.len() != 2 {
+            return Err(LoadError::Format(format!("{} does not hold a lowest and highest height", path.display())));
+        }
+
+        return Ok(Some((values[0], values[1])));
+    }
+
+    /// Heights are normalised to 0..1.
+    pub fn load_png(path: &Path) -> Result<Self, LoadError> {
+        let file = try!(File::open(path));
+        let mut decoder = image::png::PNGDecoder::new(BufReader::new(file));
+
+        let (width, depth) = try!(decoder.dimensions());
+        let color = try!(decoder.colortype());
+
+        let heights: Vec<f32> = match (color, try!(decoder.read_image())) {
+            (ColorType::Gray(16), DecodingResult::U16(data)) => data.iter().map(|&h| h as f32 / 65535.0).collect(),
+            // The decoder hands 16-bit samples over as raw big-endian byte pairs
+            (ColorType::Gray(16), DecodingResult::U8(data)) => data.chunks(2)
+                .filter(|b| b.len() == 2)
+                .map(|b| ((b[0] as u16) << 8 | b[1] as u16) as f32 / 65535.0)
+                .collect(),
+            (ColorType::Gray(8), DecodingResult::U8(data)) => data.iter().map(|&h| h as f32 / 255.0).collect(),
+            _ => return Err(LoadError::Format(format!("{} is not a grayscale PNG", path.display()))),
+        };
+
+        return Ok(HeightField::from_heights(width as usize, depth as usize, heights));
+    }
+
+    /// Raw files carry no header, so they are expected to be square.
+    pub fn load_raw(path: &Path, format: RawFormat) -> Result<Self, LoadError> {
+        let mut bytes = Vec::new();
+        try!(try!(File::open(path)).read_to_end(&mut bytes));
+
+        let heights: Vec<f32> = match format {
+            RawFormat::R16 => bytes.chunks(2)
+                .filter(|b| b.len() == 2)
+                .map(|b| (b[0] as u16 | (b[1] as u16) << 8) as f32 / 65535.0)
+                .collect(),
+            RawFormat::R32 => bytes.chunks(4)
+                .filter(|b| b.len() == 4)
+                .map(|b| {
+                    let bits = b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24;
+                    unsafe { ::std::mem::transmute::<u32, f32>(bits) }
+                })
+                .collect(),
+        };
+
+        let side = (heights.len() as f64).sqrt() as usize;
+        if side == 0 || side * side != heights.len() {
+            return Err(LoadError::Format(format!("{} is not a square height file", path.display())));
+        }
+
+        return Ok(HeightField::from_heights(side, side, heights));
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn heights(&self) -> &[f32] {
+        &self.heights
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, height: f32) {
+        self.heights[z * self.width + x] = height;
+    }
+
+    /// Bilinear interpolation between the four nearest samples.
+    /// Coordinates outside the grid are clamped to its edge.
+    pub fn sample(&self, x: f32, z: f32) -> f32 {
+        let max_x = (self.width - 1) as f32;
+        let max_z = (self.depth - 1) as f32;
+        let x = if x.is_nan() { 0.0 } else { x.max(0.0).min(max_x) };
+        let z = if z.is_nan() { 0.0 } else { z.max(0.0).min(max_z) };
+
+        let x0 = x.floor() as usize;
+        let z0 = z.floor() as usize;
+        let x1 = (x0 + 1).min(self.width - 1);
+        let z1 = (z0 + 1).min(self.depth - 1);
+        let fx = x - x0 as f32;
+        let fz = z - z0 as f32;
+
+        let top = self.get(x0, z0) * (1.0 - fx) + self.get(x1, z0) * fx;
+        let bottom = self.get(x0, z1) * (1.0 - fx) + self.get(x1, z1) * fx;
+
+        return top * (1.0 - fz) + bottom * fz;
+    }
+}
+
+impl HeightSource for HeightField {
+    fn get_height(&self, x: f32, z: f32) -> f32 {
+        self.sample(x, z)
+    }
+
+    fn extent(&self) -> Option<(f32, f32)> {
+        Some(((self.width - 1) as f32, (self.depth - 1) as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use export;
+    use super::HeightField;
+
+    #[test]
+    fn sixteen_bit_png_round_trips() {
+        let (width, depth) = (7, 5);
+        let heights: Vec<f32> = (0..width * depth).map(|i| (i as f32 * 0.37).sin() * 3.0).collect();
+        let field = HeightField::from_heights(width, depth, heights.clone());
+
+        let path = env::temp_dir().join("meadow_sixteen_bit_png_round_trips.png");
+        export::write_heightfield_png(&path, &field).unwrap();
+        let loaded = HeightField::load_png(&path);
+        let restored = HeightField::load_heightmap(&path, 1.0);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&path.with_extension("range")).unwrap();
+        let loaded = loaded.unwrap();
+        let restored = restored.unwrap();
+
+        assert_eq!(loaded.width(), width);
+        assert_eq!(loaded.depth(), depth);
+
+        // The PNG holds the heights stretched to 0..1
+        let min = heights.iter().fold(::std::f32::INFINITY, |a, &b| a.min(b));
+        let max = heights.iter().fold(::std::f32::NEG_INFINITY, |a, &b| a.max(b));
+        for (&original, &read) in heights.iter().zip(loaded.heights()) {
+            assert!((read - (original - min) / (max - min)).abs() < 1.0 / 65535.0);
+        }
+
+        // While loading through the range file gives back the real heights
+        for (&original, &read) in heights.iter().zip(restored.heights()) {
+            assert!((read - original).abs() < (max - min) / 65535.0 + 1e-5);
+        }
+    }
+}
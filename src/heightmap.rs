@@ -2,9 +2,10 @@ extern crate cgmath;
 use cgmath::Vector2;
 
 extern crate rand;
-use rand::Rng;
+use rand::{Rng, SeedableRng, XorShiftRng};
 
 use std::f32::consts::PI;
+use std::sync::Arc;
 
 fn lerp(a: f32, b: f32, f: f32) -> f32 {
     a * (1.0 - f) + b * f
@@ -14,6 +15,10 @@ fn smooth(v: f32) -> f32 {
     v * v * (3.0 - 2.0 * v)
 }
 
+fn smooth_derivative(v: f32) -> f32 {
+    6.0 * v * (1.0 - v)
+}
+
 fn random_gradient<R: Rng>(r: &mut R) -> Vector2<f32> {
     let v = PI * 2.0 * r.gen::<f32>();
     Vector2 { x: v.cos(), y: v.sin() }
@@ -23,39 +28,100 @@ fn gradient(orig: Vector2<f32>, grad: Vector2<f32>, p: Vector2<f32>) -> f32 {
     (p.x - orig.x) * grad.x + (p.y - orig.y) * grad.y
 }
 
+/// Creates a deterministic random number generator from a user supplied seed.
+pub fn seeded_rng(seed: u32) -> XorShiftRng {
+    // XorShift must never be seeded with all zeros, so mix in some constants
+    XorShiftRng::from_seed([seed, seed ^ 0x9E3779B9, 0x243F6A88, 0xB7E15162])
+}
+
+/// Parameters for the fractal Brownian motion summed up by `get_height`.
+#[derive(Copy, Clone, Debug)]
+pub struct HeightParams {
+    /// Number of noise layers summed together.
+    pub octaves: u32,
+    /// Frequency multiplier between two consecutive octaves.
+    pub lacunarity: f32,
+    /// Amplitude multiplier between two consecutive octaves.
+    pub gain: f32,
+    /// Frequency of the first octave.
+    pub frequency: f32,
+    /// Amplitude of the first octave.
+    pub amplitude: f32,
+    /// Constant height added to the sum.
+    pub base: f32,
+}
+
+impl Default for HeightParams {
+    /// Rolling hills from the low frequency octave, with some higher frequency noise on top.
+    fn default() -> Self {
+        HeightParams {
+            octaves: 2,
+            lacunarity: 2.5,
+            gain: 0.2,
+            frequency: 0.08,
+            amplitude: 5.0,
+            base: 0.5,
+        }
+    }
+}
+
+/// Gradient noise over a lattice that wraps around every `size` cells, so it is
+/// periodic and defined for every finite coordinate.
 pub struct NoiseContext {
-    rgradients: Vec<Vec<Vector2<f32>>>,
+    gradients: Vec<Vector2<f32>>,
+    permutation: Vec<usize>,
+    params: HeightParams,
+}
+
+/// Wraps a lattice coordinate into `0..size`, also for negative or huge coordinates.
+fn wrap(v: f32, size: usize) -> usize {
+    let n = size as f32;
+    let r = v % n;
+    let r = if r < 0.0 { r + n } else { r };
+
+    // Adding n to a tiny negative remainder can round up to n itself
+    return (r as usize) % size;
 }
 
 impl NoiseContext {
-    pub fn new(size: usize) -> Self {
-        let mut rng = rand::thread_rng();
-
-        let mut grad_data = Vec::with_capacity(size + 1);
-        for _ in 0..(size+1) {
-            let mut row_data = Vec::with_capacity(size + 1);
-            for _ in 0..(size+1) {
-                let gradient = random_gradient(&mut rng);
-                row_data.push(gradient);
-            }
-            grad_data.push(row_data);
+    /// The same seed always yields the same gradients, and thus the same heights.
+    pub fn with_seed(size: usize, seed: u32) -> Self {
+        let mut rng = seeded_rng(seed);
+
+        let mut gradients = Vec::with_capacity(size);
+        for _ in 0..size {
+            gradients.push(random_gradient(&mut rng));
         }
 
+        let mut permutation: Vec<usize> = (0..size).collect();
+        rng.shuffle(&mut permutation);
+
         return NoiseContext {
-            rgradients: grad_data,
+            gradients: gradients,
+            permutation: permutation,
+            params: HeightParams::default(),
         };
     }
 
+    pub fn height_params(&self) -> &HeightParams {
+        &self.params
+    }
+
+    pub fn set_height_params(&mut self, params: HeightParams) {
+        self.params = params;
+    }
+
     fn get_gradients(&self, x: f32, y: f32) -> ([Vector2<f32>; 4], [Vector2<f32>; 4]) {
         let x0f = x.floor();
         let y0f = y.floor();
         let x1f = x0f + 1.0;
         let y1f = y0f + 1.0;
 
-        let x0 = x0f as i32;
-        let y0 = y0f as i32;
-        let x1 = x0 + 1;
-        let y1 = y0 + 1;
+        let size = self.permutation.len();
+        let x0 = wrap(x0f, size);
+        let y0 = wrap(y0f, size);
+        let x1 = (x0 + 1) % size;
+        let y1 = (y0 + 1) % size;
 
         ([self.get_gradient(x0, y0), self.get_gradient(x1, y0),
           self.get_gradient(x0, y1), self.get_gradient(x1, y1)],
@@ -63,11 +129,17 @@ impl NoiseContext {
           Vector2 { x: x0f, y: y1f }, Vector2 { x: x1f, y: y1f }])
     }
 
-    fn get_gradient(&self, x: i32, y: i32) -> Vector2<f32> {
-        return self.rgradients[y as usize][x as usize];
+    fn get_gradient(&self, x: usize, y: usize) -> Vector2<f32> {
+        let size = self.permutation.len();
+        return self.gradients[self.permutation[(self.permutation[x] + y) % size]];
     }
 
-    fn get(&self, x: f32, y: f32) -> f32 {
+    pub fn get(&self, x: f32, y: f32) -> f32 {
+        // There is no sensible lattice cell for infinities or NaN
+        if !x.is_finite() || !y.is_finite() {
+            return 0.0;
+        }
+
         let p = Vector2 {x: x, y: y};
         let (gradients, origins) = self.get_gradients(x, y);
 
@@ -84,16 +156,334 @@ impl NoiseContext {
         lerp(vx0, vx1, fy)
     }
 
-    pub fn get_height(&self, x: f32, z: f32) -> f32 {
-        // Low frequencies generates rolling hills
-        let x1 = (x + 0.5) * 0.08;
-        let z1 = (z + 0.5) * 0.08;
+    /// Returns the same value as `get`, along with its partial derivatives along x and y.
+    pub fn get_with_derivative(&self, x: f32, y: f32) -> (f32, f32, f32) {
+        if !x.is_finite() || !y.is_finite() {
+            return (0.0, 0.0, 0.0);
+        }
+
+        let p = Vector2 {x: x, y: y};
+        let (g, origins) = self.get_gradients(x, y);
+
+        let v0 = gradient(origins[0], g[0], p);
+        let v1 = gradient(origins[1], g[1], p);
+        let v2 = gradient(origins[2], g[2], p);
+        let v3 = gradient(origins[3], g[3], p);
+
+        let tx = x - origins[0].x;
+        let ty = y - origins[0].y;
+        let fx = smooth(tx);
+        let fy = smooth(ty);
+        let dfx = smooth_derivative(tx);
+        let dfy = smooth_derivative(ty);
 
-        // Higher frequencies generates noise
-        let x2 = (x + 0.5) * 0.2;
-        let z2 = (z + 0.5) * 0.2;
+        // The bilinear blend written out, so it can be differentiated term by term
+        let k = v0 - v1 - v2 + v3;
+        let value = v0 + fx * (v1 - v0) + fy * (v2 - v0) + fx * fy * k;
 
-        return 0.5 + 5.0 * self.get(x1, z1) + self.get(x2, z2);
+        let dx = g[0].x + fx * (g[1].x - g[0].x) + fy * (g[2].x - g[0].x)
+            + fx * fy * (g[0].x - g[1].x - g[2].x + g[3].x)
+            + dfx * ((v1 - v0) + fy * k);
+        let dy = g[0].y + fx * (g[1].y - g[0].y) + fy * (g[2].y - g[0].y)
+            + fx * fy * (g[0].y - g[1].y - g[2].y + g[3].y)
+            + dfy * ((v2 - v0) + fx * k);
+
+        return (value, dx, dy);
     }
 }
 
+/// Unit length normal of a height field with the given partial derivatives.
+pub fn normal_from_derivative(dx: f32, dz: f32) -> [f32; 3] {
+    let length = (dx * dx + 1.0 + dz * dz).sqrt();
+    [-dx / length, 1.0 / length, -dz / length]
+}
+
+/// Anything that can tell the height of the ground at a point.
+pub trait HeightSource {
+    fn get_height(&self, x: f32, z: f32) -> f32;
+
+    /// Height along with its partial derivatives along x and z.
+    /// Sources without an analytic derivative fall back on central differences.
+    fn get_height_with_derivative(&self, x: f32, z: f32) -> (f32, f32, f32) {
+        let e = 0.01;
+        let dx = (self.get_height(x + e, z) - self.get_height(x - e, z)) / (2.0 * e);
+        let dz = (self.get_height(x, z + e) - self.get_height(x, z - e)) / (2.0 * e);
+        (self.get_height(x, z), dx, dz)
+    }
+
+    /// Unit length surface normal.
+    fn get_normal(&self, x: f32, z: f32) -> [f32; 3] {
+        let (_, dx, dz) = self.get_height_with_derivative(x, z);
+        normal_from_derivative(dx, dz)
+    }
+
+    /// Width and depth of the area starting at the origin that the source covers,
+    /// or `None` when it goes on forever.
+    fn extent(&self) -> Option<(f32, f32)> {
+        None
+    }
+}
+
+/// A height source that can be shared with worker threads.
+pub type SharedHeightSource = Arc<HeightSource + Send + Sync>;
+
+/// Sums `params.octaves` layers of `layer`, each at a higher frequency and lower amplitude.
+fn fractal<F: Fn(f32, f32) -> f32>(params: &HeightParams, x: f32, z: f32, layer: F) -> f32 {
+    let mut frequency = params.frequency;
+    let mut amplitude = params.amplitude;
+    let mut sum = 0.0;
+
+    for _ in 0..params.octaves {
+        sum += amplitude * layer((x + 0.5) * frequency, (z + 0.5) * frequency);
+        frequency *= params.lacunarity;
+        amplitude *= params.gain;
+    }
+
+    return sum;
+}
+
+/// Like `fractal`, but for layers that also return their derivatives.
+fn fractal_with_derivative<F: Fn(f32, f32) -> (f32, f32, f32)>(params: &HeightParams, x: f32, z: f32, layer: F) -> (f32, f32, f32) {
+    let mut frequency = params.frequency;
+    let mut amplitude = params.amplitude;
+    let (mut sum, mut dx, mut dz) = (0.0, 0.0, 0.0);
+
+    for _ in 0..params.octaves {
+        let (value, ldx, ldz) = layer((x + 0.5) * frequency, (z + 0.5) * frequency);
+        sum += amplitude * value;
+        // Chain rule, the layer was evaluated at scaled coordinates
+        dx += amplitude * frequency * ldx;
+        dz += amplitude * frequency * ldz;
+
+        frequency *= params.lacunarity;
+        amplitude *= params.gain;
+    }
+
+    return (sum, dx, dz);
+}
+
+/// Plain fractal Brownian motion.
+impl HeightSource for NoiseContext {
+    fn get_height(&self, x: f32, z: f32) -> f32 {
+        self.params.base + fractal(&self.params, x, z, |x, z| self.get(x, z))
+    }
+
+    fn get_height_with_derivative(&self, x: f32, z: f32) -> (f32, f32, f32) {
+        let (height, dx, dz) = fractal_with_derivative(&self.params, x, z, |x, z| self.get_with_derivative(x, z));
+        (self.params.base + height, dx, dz)
+    }
+}
+
+/// Ridged multifractal noise, sharp mountain ridges where the noise crosses zero.
+pub struct RidgedNoise {
+    noise: NoiseContext,
+    sharpness: f32,
+}
+
+impl RidgedNoise {
+    pub fn new(noise: NoiseContext, sharpness: f32) -> Self {
+        RidgedNoise { noise: noise, sharpness: sharpness }
+    }
+}
+
+impl HeightSource for RidgedNoise {
+    fn get_height(&self, x: f32, z: f32) -> f32 {
+        let params = self.noise.height_params();
+
+        let mut frequency = params.frequency;
+        let mut amplitude = params.amplitude;
+        let mut height = params.base;
+
+        // Higher octaves only add detail where the previous ones formed a ridge
+        let mut weight = 1.0;
+        for _ in 0..params.octaves {
+            let n = self.noise.get((x + 0.5) * frequency, (z + 0.5) * frequency);
+            let ridge = 1.0 - 2.0 * n.abs();
+            let signal = ridge * ridge * weight;
+
+            height += amplitude * signal;
+            weight = (signal * self.sharpness).max(0.0).min(1.0);
+
+            frequency *= params.lacunarity;
+            amplitude *= params.gain;
+        }
+
+        return height;
+    }
+}
+
+/// Billowy noise, puffy rounded hills separated by creases.
+pub struct BillowNoise {
+    noise: NoiseContext,
+}
+
+impl BillowNoise {
+    pub fn new(noise: NoiseContext) -> Self {
+        BillowNoise { noise: noise }
+    }
+}
+
+impl HeightSource for BillowNoise {
+    fn get_height(&self, x: f32, z: f32) -> f32 {
+        let params = self.noise.height_params();
+        params.base + fractal(params, x, z, |x, z| 2.0 * self.noise.get(x, z).abs() - 0.5)
+    }
+
+    fn get_height_with_derivative(&self, x: f32, z: f32) -> (f32, f32, f32) {
+        let params = self.noise.height_params();
+        let (height, dx, dz) = fractal_with_derivative(params, x, z, |x, z| {
+            let (n, dx, dz) = self.noise.get_with_derivative(x, z);
+            let sign = if n < 0.0 { -1.0 } else { 1.0 };
+            (2.0 * n.abs() - 0.5, 2.0 * sign * dx, 2.0 * sign * dz)
+        });
+        (params.base + height, dx, dz)
+    }
+}
+
+/// Fractal noise looked up at coordinates displaced by another fractal noise.
+pub struct WarpedNoise {
+    noise: NoiseContext,
+    strength: f32,
+}
+
+impl WarpedNoise {
+    /// `strength` is the largest displacement of the lookup, in world units.
+    pub fn new(noise: NoiseContext, strength: f32) -> Self {
+        WarpedNoise { noise: noise, strength: strength }
+    }
+}
+
+impl HeightSource for WarpedNoise {
+    fn get_height(&self, x: f32, z: f32) -> f32 {
+        let params = self.noise.height_params();
+        let layer = |x: f32, z: f32| self.noise.get(x, z);
+
+        // Offset lookups so the two warp directions are not correlated
+        let scale = self.strength / params.amplitude;
+        let wx = scale * fractal(params, x + 31.7, z + 11.3, &layer);
+        let wz = scale * fractal(params, x - 17.9, z + 53.1, &layer);
+
+        return params.base + fractal(params, x + wx, z + wz, &layer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grass;
+    use grass::GrassSettings;
+    use rendering::GrassAttrs;
+    use super::{wrap, HeightParams, HeightSource, NoiseContext};
+
+    const SIZE: usize = 256;
+
+    // Coordinates that used to trip up the lattice lookup
+    const AWKWARD: [f32; 14] = [
+        -0.3, -17.25, -255.9, -1e-30, -1e-7, 0.0, 256.0, -512.0, 768.0, 3.0,
+        1e7, -1e7, 1e30, -1e30,
+    ];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 5e-3 * (1.0 + a.abs().max(b.abs()))
+    }
+
+    /// One octave per lattice cell, doubling from there, so the heights repeat every `SIZE` too.
+    fn periodic_noise() -> NoiseContext {
+        let mut noise = NoiseContext::with_seed(SIZE, 99);
+        noise.set_height_params(HeightParams { octaves: 3, lacunarity: 2.0, gain: 0.5, frequency: 1.0, amplitude: 1.0, base: 0.0 });
+        return noise;
+    }
+
+    fn scatter(noise: &NoiseContext) -> Vec<GrassAttrs> {
+        grass::scatter_grass(noise, &GrassSettings::uniform(0.5, 0.5), 17, -8.0, 24.0, 7)
+    }
+
+    fn same_grass(a: &[GrassAttrs], b: &[GrassAttrs]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| {
+            a.offset == b.offset && a.rand_factor == b.rand_factor && a.species == b.species && a.ground_normal == b.ground_normal
+        })
+    }
+
+    fn heights(noise: &NoiseContext) -> Vec<f32> {
+        (0..100).map(|i| noise.get_height(i as f32 * 3.7 - 150.0, i as f32 * -1.3 + 20.0)).collect()
+    }
+
+    #[test]
+    fn wrap_stays_inside_lattice() {
+        for &v in AWKWARD.iter() {
+            assert!(wrap(v, SIZE) < SIZE);
+        }
+        // The remainders of these round up to the size itself when made positive
+        assert!(wrap(-1e-30, SIZE) < SIZE);
+        assert!(wrap(-::std::f32::MIN_POSITIVE, SIZE) < SIZE);
+    }
+
+    #[test]
+    fn awkward_coordinates_give_finite_noise() {
+        let noise = periodic_noise();
+        for &x in AWKWARD.iter() {
+            for &y in AWKWARD.iter() {
+                assert!(noise.get(x, y).is_finite());
+
+                let (value, dx, dy) = noise.get_with_derivative(x, y);
+                assert!(value.is_finite() && dx.is_finite() && dy.is_finite());
+
+                assert!(noise.get_height(x, y).is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn noise_vanishes_on_lattice_points() {
+        let noise = periodic_noise();
+        for &x in [0.0, 3.0, 256.0, -512.0, 768.0, -1.0, 1e7, -1e7].iter() {
+            assert_eq!(noise.get(x, 5.0), 0.0);
+            assert_eq!(noise.get(-7.0, x), 0.0);
+        }
+    }
+
+    #[test]
+    fn noise_repeats_every_size() {
+        let noise = periodic_noise();
+        let n = SIZE as f32;
+        for &x in [-0.3, -17.25, -255.9, -1e-7, 0.4, 3.0, 100.6].iter() {
+            for &y in [-0.7, -128.5, 0.0, 12.125, 200.2].iter() {
+                for &(px, py) in [(x + n, y), (x, y - n), (x - 2.0 * n, y + 3.0 * n)].iter() {
+                    assert!(close(noise.get(x, y), noise.get(px, py)));
+
+                    let (v0, dx0, dy0) = noise.get_with_derivative(x, y);
+                    let (v1, dx1, dy1) = noise.get_with_derivative(px, py);
+                    assert!(close(v0, v1) && close(dx0, dx1) && close(dy0, dy1));
+
+                    // The world coordinates are moved by half a unit before scaling
+                    assert!(close(noise.get_height(x - 0.5, y - 0.5), noise.get_height(px - 0.5, py - 0.5)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_terrain() {
+        let a = NoiseContext::with_seed(256, 1234);
+        let b = NoiseContext::with_seed(256, 1234);
+
+        assert_eq!(a.permutation, b.permutation);
+        for (ga, gb) in a.gradients.iter().zip(b.gradients.iter()) {
+            assert_eq!((ga.x, ga.y), (gb.x, gb.y));
+        }
+
+        assert_eq!(heights(&a), heights(&b));
+        assert!(same_grass(&scatter(&a), &scatter(&b)));
+    }
+
+    #[test]
+    fn other_seed_gives_other_terrain() {
+        let a = NoiseContext::with_seed(256, 1234);
+        let b = NoiseContext::with_seed(256, 1235);
+
+        assert!(a.permutation != b.permutation);
+        assert!(a.gradients.iter().zip(b.gradients.iter()).any(|(ga, gb)| (ga.x, ga.y) != (gb.x, gb.y)));
+
+        assert!(heights(&a) != heights(&b));
+        assert!(!same_grass(&scatter(&a), &scatter(&b)));
+    }
+}
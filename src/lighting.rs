@@ -0,0 +1,38 @@
+/// How the sun and the sky light the terrain, the grass and the props.
+#[derive(Copy, Clone, Debug)]
+pub struct LightParams {
+    /// Multiplies the sun colour for direct light.
+    pub sun_intensity: f32,
+    /// Ambient light from straight above, at full daylight.
+    pub sky_color: [f32; 3],
+    /// Ambient light bounced off the ground, from straight below.
+    pub ground_color: [f32; 3],
+    pub ambient_intensity: f32,
+    /// Sunlight passing through the grass blades, 0 for opaque blades.
+    pub translucency: f32,
+    /// How narrow the bright halo around the sun is when looking through the blades.
+    pub translucency_power: f32,
+}
+
+impl Default for LightParams {
+    fn default() -> Self {
+        LightParams {
+            sun_intensity: 1.0,
+            sky_color: [0.45, 0.6, 0.9],
+            ground_color: [0.3, 0.25, 0.15],
+            ambient_intensity: 0.4,
+            translucency: 0.6,
+            translucency_power: 4.0,
+        }
+    }
+}
+
+impl LightParams {
+    /// Hemispheric ambient light from above and from below, dimmed by `daylight` from 0 at night to 1 by day.
+    pub fn ambient(&self, daylight: f32) -> ([f32; 3], [f32; 3]) {
+        // Starlight and moonlight keep the night from going fully black
+        let strength = self.ambient_intensity * (0.05 + 0.95 * daylight);
+        let scale = |color: [f32; 3]| [color[0] * strength, color[1] * strength, color[2] * strength];
+        (scale(self.sky_color), scale(self.ground_color))
+    }
+}
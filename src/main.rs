@@ -8,22 +8,64 @@ extern crate glutin;
 extern crate rand;
 
 mod support;
+mod options;
 mod programs;
 mod rendering;
 mod heightmap;
+mod heightfield;
+mod erosion;
 mod terrain;
+mod chunks;
+mod grass;
+mod poisson;
+mod props;
+mod wind;
+mod trample;
+mod sky;
+mod lighting;
+mod deferred;
+mod export;
 
-use rendering::Vertex;
+use heightmap::SharedHeightSource;
+use std::sync::Arc;
+use options::Generator;
+use std::io;
 use std::io::Cursor;
+use std::io::Write;
+use std::path::Path;
+use std::process;
 use glium::Surface;
 use cgmath::FixedArray;
 
 static NOISE_SAMPLES: usize = 128;
 
+// Layers of the grass texture array, picked by `Species::texture`. There is only
+// the grass so far, more layers go here as textures for other plants come in
+static GRASS_TEXTURES: [&'static [u8]; 1] = [include_bytes!("textures/grass.png")];
+
 
 fn main() {
     use glium::DisplayBuild;
 
+    let options = options::Options::from_env();
+    println!("Meadow seed: {}", options.seed);
+
+    // Before opening the window, so a bad --prop path does not flash one up
+    let mut prop_vertices = Vec::new();
+    for kind in options.props.iter() {
+        match kind.mesh.load() {
+            Ok(vertices) => prop_vertices.push(vertices),
+            Err(err) => {
+                let source = match kind.mesh {
+                    props::MeshSource::File(ref path) => path.clone(),
+                    props::MeshSource::Builtin(_) => "built in mesh".to_string(),
+                };
+                writeln!(io::stderr(), "Could not load prop {}: {}", source, err).unwrap();
+                process::exit(1);
+            },
+        }
+    }
+
     let display = glium::glutin::WindowBuilder::new()
         .with_depth_buffer(24)
         .with_dimensions(1920, 1080)
@@ -31,58 +73,146 @@ fn main() {
         .build_glium()
         .unwrap();
 
-    let noise_data = heightmap::NoiseContext::new(NOISE_SAMPLES);
+    let mut heights: SharedHeightSource = match options.heightmap {
+        Some(ref path) => Arc::new(heightfield::HeightField::load_heightmap(Path::new(path), options.heightmap_scale).unwrap()),
+        None => {
+            let mut noise_data = heightmap::NoiseContext::with_seed(NOISE_SAMPLES, options.seed);
+            noise_data.set_height_params(options.height_params);
+
+            match options.generator {
+                Generator::Fbm => Arc::new(noise_data),
+                Generator::Ridged => Arc::new(heightmap::RidgedNoise::new(noise_data, 2.0)),
+                Generator::Billow => Arc::new(heightmap::BillowNoise::new(noise_data)),
+                Generator::Warped => Arc::new(heightmap::WarpedNoise::new(noise_data, 4.0)),
+            }
+        },
+    };
+
+    // Like a loaded heightmap, the eroded field is finite and the terrain stops at its edges
+    if options.erode {
+        let size = options.size as usize;
+        let mut field = heightfield::HeightField::from_source(&*heights, size, size);
+        erosion::thermal(&mut field, &options.thermal_params);
+        erosion::hydraulic(&mut field, &options.hydraulic_params, options.seed);
+        heights = Arc::new(field);
+    }
+
+    let density: Box<grass::GrassDensity + Send + Sync> = match options.grass_mask {
+        Some(ref path) => {
+            let mask = heightfield::HeightField::load(Path::new(path), 1.0).unwrap();
+            Box::new(grass::MaskDensity::new(mask, options.grass_mask_scale))
+        },
+        None => Box::new(grass::NoiseDensity::new(options.seed)),
+    };
+    let grass_settings = Arc::new(grass::GrassSettings {
+        density: density,
+        rules: options.placement_rules.clone(),
+        species: grass::default_species(),
+        min_spacing: options.grass_spacing,
+    });
+
+    if let Some(ref dir) = options.export {
+        export::export_meadow(Path::new(dir), &*heights, &*grass_settings, options.size, options.seed).unwrap();
+        println!("Exported meadow to {}", dir);
+    }
 
     // Load textures from disk
-    let grass_png = image::load(Cursor::new(&include_bytes!("textures/grass.png")[..]), image::PNG).unwrap();
-    let grass_texture = glium::texture::CompressedTexture2d::new(&display, grass_png);
+    let terrain_png = image::load(Cursor::new(&GRASS_TEXTURES[0][..]), image::PNG).unwrap();
+    let terrain_texture = glium::texture::CompressedTexture2d::new(&display, terrain_png);
+
+    let grass_texture = glium::texture::Texture2dArray::new(&display, GRASS_TEXTURES.iter().map(|bytes| {
+        image::load(Cursor::new(&bytes[..]), image::PNG).unwrap()
+    }).collect::<Vec<_>>());
+    // Heights and tints go beyond 1, so they need an unclamped float format
+    let species_texture = glium::texture::Texture2d::with_format(&display, grass::species_texels(&grass_settings.species), glium::texture::UncompressedFloatFormat::F32F32F32F32, false);
 
     let mask_png = image::load(Cursor::new(&include_bytes!("textures/grass_mask.png")[..]), image::PNG).unwrap();
     let mask_texture = glium::texture::CompressedTexture2d::new(&display, mask_png);
 
     let pm = programs::ProgramManager::new();
-    let terrain_program = pm.create(&display, &programs::ShaderBundle::new("simple.vs", "terrain.fs", None, None, None)).unwrap();
+    let terrain_program = pm.create(&display, &programs::ShaderBundle::new("terrain.vs", "terrain.fs", None, None, None)).unwrap();
+    let wireframe_program = pm.create(&display, &programs::ShaderBundle::new("wireframe.vs", "wireframe.fs", None, None, None)).unwrap();
     let grass_program = pm.create(&display, &programs::ShaderBundle::new("grass.vs", "grass.fs", Some("grass.gs"), None, None)).unwrap();
+    let prop_program = pm.create(&display, &programs::ShaderBundle::new("prop.vs", "prop.fs", None, None, None)).unwrap();
 
-    let mut terrain = terrain::Terrain::new(&display, &noise_data, terrain_program, grass_program);
+    let prop_meshes = prop_vertices.into_iter().map(|vertices| glium::VertexBuffer::new(&display, vertices)).collect();
+
+    let builder = chunks::ChunkBuilder::new(heights.clone(), grass_settings.clone(), Arc::new(options.props.clone()), options.size, options.seed);
+    let mut terrain = terrain::Terrain::new(&display, builder, options.size, options.view_radius, prop_meshes, terrain_program, grass_program, prop_program, wireframe_program);
+    terrain.set_grass_lod(options.grass_lod);
 
     let (w, h) = display.get_window().unwrap().get_inner_size().unwrap();
     let mut camera = support::camera::CameraState::new(w, h);
 
     // Setup deferred rendering
-    let quad = rendering::RenderData::new(&display,
-            vec![
-                Vertex { position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 0.0] },
-                Vertex { position: [1.0, -1.0, 0.0], tex_coords: [1.0, 0.0] },
-                Vertex { position: [1.0, 1.0, 0.0], tex_coords: [1.0, 1.0] },
-                Vertex { position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 1.0] },
-            ],
-            glium::index::TrianglesList(vec![0u16, 1, 2, 0, 2, 3])
-        );
-
-    let texture1 = glium::texture::Texture2d::new_empty(&display, glium::texture::UncompressedFloatFormat::F32F32F32F32, w, h);
-    let depthtexture = glium::texture::DepthTexture2d::new_empty(&display, glium::texture::DepthFormat::F32, w, h);
-    let output = &[("output1", &texture1)];
-    let mut framebuffer = glium::framebuffer::MultiOutputFrameBuffer::with_depth_buffer(&display, output, &depthtexture);
-    let composition_program = pm.create(&display, &programs::ShaderBundle::new("null.vs", "simple.fs", None, None, None)).unwrap();
+    let lighting_program = pm.create(&display, &programs::ShaderBundle::new("deferred.vs", "deferred.fs", None, None, None)).unwrap();
+    let mut deferred_renderer = deferred::DeferredRenderer::new(&display, lighting_program, w, h);
+
+    let mut wind = wind::WindField::new(options.wind_params, options.seed);
+    // Signed float texels, normalized formats would clamp the wind to positive x and z
+    let wind_texture = glium::texture::Texture2d::with_format(&display, wind.texels(), glium::texture::UncompressedFloatFormat::F32F32, false);
+
+    let sky_program = pm.create(&display, &programs::ShaderBundle::new("sky.vs", "sky.fs", None, None, None)).unwrap();
+    let mut sky = sky::Sky::new(&display, sky_program, options.sky_params);
+
+    let mut trample_map = trample::TrampleMap::new();
+    let trample_texture = glium::texture::Texture2d::with_format(&display, trample_map.texels(), glium::texture::UncompressedFloatFormat::F32F32, false);
+    // Anything else walking around the meadow, besides the camera
+    let actors: Vec<trample::Actor> = Vec::new();
 
     // the main loop
-    let mut tick_number = 0;
+    let mut stats = support::stats::FrameStats::new(options.stats);
     support::start_loop(|| {
-        tick_number += 1;
-        camera.update(&noise_data);
+        // Simulations advance by the real time passed, whatever the frame rate
+        let dt = stats.delta();
+        camera.update(&*heights);
+        stats.time("terrain update", || terrain.stream(&display, &camera));
+
+        let position = camera.get_position();
+        stats.time("wind update", || {
+            wind.update(dt, position.x, position.z);
+            wind_texture.write(glium::Rect { left: 0, bottom: 0, width: wind_texture.get_width(), height: wind_texture.get_height().unwrap() }, wind.texels());
+        });
+        stats.time("trample update", || {
+            let mut walkers = actors.clone();
+            walkers.push(trample::Actor { x: position.x, z: position.z, radius: 0.6 });
+            trample_map.update(dt, position.x, position.z, &walkers);
+            trample_texture.write(glium::Rect { left: 0, bottom: 0, width: trample_texture.get_width(), height: trample_texture.get_height().unwrap() }, trample_map.texels());
+        });
+
+        sky.tick(dt);
 
         // building the uniforms
+        let grass_lod = terrain.grass_lod();
+        let persp_matrix = camera.get_perspective().into_fixed();
+        let view_matrix = camera.get_view().into_fixed();
         let uniforms = uniform! {
             // Camera uniforms
-            persp_matrix: camera.get_perspective().into_fixed(),
-            view_matrix: camera.get_view().into_fixed(),
+            persp_matrix: persp_matrix,
+            view_matrix: view_matrix,
+            eye_position: [position.x, position.y, position.z],
+
+            // Grass level of detail
+            grass_max_segments: grass_lod.max_segments,
+            grass_min_segments: grass_lod.min_segments,
+            grass_segment_distance: grass_lod.segment_distance,
+            grass_card_distance: grass_lod.card_distance,
+            grass_thin_start: grass_lod.thin_start,
+            grass_max_distance: grass_lod.max_distance,
+
+            // Wind over the square around the camera
+            wind_field: &wind_texture,
+            wind_origin: wind.origin(),
+            wind_extent: wind.extent(),
 
-            // Calculate the wind somehow
-            windforce: cgmath::vec3((tick_number as f32 / 100.0).sin() / 2.0, 0.0, 0.0).into_fixed(),
+            // Grass bent down by the camera and the actors
+            trample_map: &trample_texture,
+            trample_origin: trample_map.origin(),
+            trample_extent: trample_map.extent(),
 
-            texture_unit: &grass_texture,
+            texture_unit: &terrain_texture,
             grass_texture_unit: &grass_texture,
+            species_data: &species_texture,
             mask_texture_unit: &mask_texture
         };
 
@@ -95,26 +225,43 @@ fn main() {
             .. std::default::Default::default()
         };
 
-        // First pass rendering
-        framebuffer.clear_color_and_depth((0.8, 0.95, 0.99, 0.0), 1.0);
-        terrain.render(&display, &mut framebuffer, &uniforms, &params);
+        // First pass rendering, into the G-buffer
+        let frustum = camera.get_frustum();
+        let counts = {
+            let mut framebuffer = deferred_renderer.gbuffer(&display);
+            stats.time("sky render", || sky.render(&mut framebuffer, persp_matrix, view_matrix));
+            stats.time("terrain render", || terrain.render(&mut framebuffer, &uniforms, &params, &frustum))
+        };
+        stats.count("grass drawn", counts.grass_drawn as u64);
+        stats.count("grass culled", counts.grass_culled as u64);
+        stats.count("props drawn", counts.props_drawn as u64);
 
-        // Final rendering to quad
-        let composition_uniforms = uniform! {
-            texture_unit: &texture1,
+        // Lighting pass to the screen
+        let view = deferred::View {
+            persp_matrix: persp_matrix,
+            view_matrix: view_matrix,
+            eye_position: [position.x, position.y, position.z],
         };
 
         let mut target = display.draw();
         target.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
-        target.draw(quad.get_vb(), quad.get_ib(), &composition_program, &composition_uniforms, &std::default::Default::default()).unwrap();
+        stats.time("lighting", || deferred_renderer.render(&mut target, &view, &sky, &options.light_params));
         target.finish();
+        stats.end_frame();
 
         // polling and handling the events received by the window
         for event in display.poll_events() {
             match event {
                 glutin::Event::Closed => return support::Action::Stop,
+                glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::F3)) => {
+                    stats.report = !stats.report;
+                },
                 ev => {
+                    if let glutin::Event::Resized(width, height) = ev {
+                        deferred_renderer.resize(&display, width, height);
+                    }
                     terrain.update(&ev);
+                    sky.update(&ev);
                     camera.process_input(&display.get_window().unwrap(), &ev);
                 },
             }
@@ -0,0 +1,192 @@
+extern crate rand;
+
+use std::env;
+use std::str::FromStr;
+
+use heightmap::HeightParams;
+use erosion::{ThermalParams, HydraulicParams};
+use grass::{GrassLod, PlacementRules};
+use props;
+use props::PropKind;
+use wind::WindParams;
+use sky::SkyParams;
+use lighting::LightParams;
+
+/// Which kind of noise shapes the terrain.
+#[derive(Copy, Clone, Debug)]
+pub enum Generator {
+    Fbm,
+    Ridged,
+    Billow,
+    Warped,
+}
+
+impl FromStr for Generator {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "fbm" => Ok(Generator::Fbm),
+            "ridged" => Ok(Generator::Ridged),
+            "billow" => Ok(Generator::Billow),
+            "warped" => Ok(Generator::Warped),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Runtime configuration, read from the command line and the environment.
+pub struct Options {
+    /// Seed used for every random decision made while building the meadow.
+    pub seed: u32,
+    /// Vertices along each side of a terrain chunk, a power of two plus one.
+    pub size: u32,
+    /// Chunks kept loaded in every direction around the camera.
+    pub view_radius: i32,
+    /// Shape of the procedural hills.
+    pub height_params: HeightParams,
+    pub generator: Generator,
+    /// Height file to use instead of procedural noise.
+    pub heightmap: Option<String>,
+    /// World height of the brightest heightmap value, unless an exported `.range` file sits next to it.
+    pub heightmap_scale: f32,
+    /// Erode the terrain before building it.
+    pub erode: bool,
+    pub thermal_params: ThermalParams,
+    pub hydraulic_params: HydraulicParams,
+    /// Grayscale image painting where grass grows, instead of noise patches.
+    pub grass_mask: Option<String>,
+    /// World units covered by one pixel of the grass mask.
+    pub grass_mask_scale: f32,
+    /// Smallest distance between two grass blades.
+    pub grass_spacing: f32,
+    /// Where grass is never placed.
+    pub placement_rules: PlacementRules,
+    /// How blades simplify in the distance.
+    pub grass_lod: GrassLod,
+    /// Meshes scattered over the terrain besides the grass, none unless any are given.
+    pub props: Vec<PropKind>,
+    pub wind_params: WindParams,
+    pub sky_params: SkyParams,
+    pub light_params: LightParams,
+    /// Directory to write the generated heights, mesh and grass into.
+    pub export: Option<String>,
+    /// Print frame timings and counts about once a second.
+    pub stats: bool,
+}
+
+// Closer blades need millions of Poisson grid cells per chunk, and no one could tell the difference
+const MIN_GRASS_SPACING: f32 = 0.05;
+
+fn parse_next<T: FromStr, I: Iterator<Item=String>>(args: &mut I, flag: &str) -> T {
+    match args.next().and_then(|value| value.parse().ok()) {
+        Some(value) => value,
+        None => panic!("Missing or invalid value for {}", flag),
+    }
+}
+
+/// Parses the next argument as an `r,g,b` colour.
+fn parse_next_color<I: Iterator<Item=String>>(args: &mut I, flag: &str) -> [f32; 3] {
+    let value: String = parse_next(args, flag);
+    let channels: Vec<f32> = value.split(',').filter_map(|c| c.trim().parse().ok()).collect();
+    if channels.len() != 3 {
+        panic!("{} takes a colour as r,g,b", flag);
+    }
+    [channels[0], channels[1], channels[2]]
+}
+
+impl Options {
+    pub fn from_env() -> Self {
+        let seed = env::var("MEADOW_SEED").ok().and_then(|value| value.parse().ok());
+
+        let mut options = Options {
+            seed: seed.unwrap_or_else(|| rand::random()),
+            size: 65,
+            view_radius: 2,
+            height_params: HeightParams::default(),
+            generator: Generator::Fbm,
+            heightmap: None,
+            heightmap_scale: 20.0,
+            export: None,
+            stats: false,
+            grass_mask: None,
+            grass_mask_scale: 1.0,
+            grass_spacing: 0.085,
+            placement_rules: PlacementRules::default(),
+            grass_lod: GrassLod::default(),
+            props: Vec::new(),
+            wind_params: WindParams::default(),
+            sky_params: SkyParams::default(),
+            light_params: LightParams::default(),
+            erode: false,
+            thermal_params: ThermalParams::default(),
+            hydraulic_params: HydraulicParams::default(),
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--seed" => options.seed = parse_next(&mut args, "--seed"),
+                "--size" => options.size = parse_next(&mut args, "--size"),
+                "--view-radius" => options.view_radius = parse_next(&mut args, "--view-radius"),
+                "--generator" => options.generator = parse_next(&mut args, "--generator"),
+                "--heightmap" => options.heightmap = Some(parse_next(&mut args, "--heightmap")),
+                "--heightmap-scale" => options.heightmap_scale = parse_next(&mut args, "--heightmap-scale"),
+                "--export" => options.export = Some(parse_next(&mut args, "--export")),
+                "--erode" => options.erode = true,
+                "--stats" => options.stats = true,
+                "--droplets" => options.hydraulic_params.iterations = parse_next(&mut args, "--droplets"),
+                "--rain" => options.hydraulic_params.rain = parse_next(&mut args, "--rain"),
+                "--evaporation" => options.hydraulic_params.evaporation = parse_next(&mut args, "--evaporation"),
+                "--sediment-capacity" => options.hydraulic_params.sediment_capacity = parse_next(&mut args, "--sediment-capacity"),
+                "--talus" => options.thermal_params.talus = parse_next(&mut args, "--talus"),
+                "--grass-mask" => options.grass_mask = Some(parse_next(&mut args, "--grass-mask")),
+                "--grass-mask-scale" => options.grass_mask_scale = parse_next(&mut args, "--grass-mask-scale"),
+                "--grass-spacing" => options.grass_spacing = parse_next(&mut args, "--grass-spacing"),
+                "--grass-segments" => options.grass_lod.max_segments = parse_next(&mut args, "--grass-segments"),
+                "--grass-min-segments" => options.grass_lod.min_segments = parse_next(&mut args, "--grass-min-segments"),
+                "--grass-card-distance" => options.grass_lod.card_distance = parse_next(&mut args, "--grass-card-distance"),
+                "--grass-distance" => options.grass_lod.max_distance = parse_next(&mut args, "--grass-distance"),
+                "--max-slope" => options.placement_rules.max_slope = Some(parse_next(&mut args, "--max-slope")),
+                "--min-altitude" => options.placement_rules.min_altitude = Some(parse_next(&mut args, "--min-altitude")),
+                "--max-altitude" => options.placement_rules.max_altitude = Some(parse_next(&mut args, "--max-altitude")),
+                "--exclude" => options.placement_rules.exclusions.push(parse_next(&mut args, "--exclude")),
+                "--prop" => options.props.push(parse_next(&mut args, "--prop")),
+                "--teapots" => options.props.extend(props::teapots()),
+                "--wind-direction" => options.wind_params.direction = parse_next::<f32, _>(&mut args, "--wind-direction").to_radians(),
+                "--wind-strength" => options.wind_params.strength = parse_next(&mut args, "--wind-strength"),
+                "--gusts" => options.wind_params.gust_strength = parse_next(&mut args, "--gusts"),
+                "--turbulence" => options.wind_params.turbulence = parse_next(&mut args, "--turbulence"),
+                "--time" => options.sky_params.time_of_day = parse_next(&mut args, "--time"),
+                "--time-speed" => options.sky_params.time_speed = parse_next(&mut args, "--time-speed"),
+                "--latitude" => options.sky_params.latitude = parse_next(&mut args, "--latitude"),
+                "--day" => options.sky_params.day_of_year = parse_next(&mut args, "--day"),
+                "--turbidity" => options.sky_params.turbidity = parse_next(&mut args, "--turbidity"),
+                "--sun-intensity" => options.light_params.sun_intensity = parse_next(&mut args, "--sun-intensity"),
+                "--ambient" => options.light_params.ambient_intensity = parse_next(&mut args, "--ambient"),
+                "--translucency" => options.light_params.translucency = parse_next(&mut args, "--translucency"),
+                "--translucency-power" => options.light_params.translucency_power = parse_next(&mut args, "--translucency-power"),
+                "--sky-color" => options.light_params.sky_color = parse_next_color(&mut args, "--sky-color"),
+                "--ground-color" => options.light_params.ground_color = parse_next_color(&mut args, "--ground-color"),
+                "--octaves" => options.height_params.octaves = parse_next(&mut args, "--octaves"),
+                "--lacunarity" => options.height_params.lacunarity = parse_next(&mut args, "--lacunarity"),
+                "--gain" => options.height_params.gain = parse_next(&mut args, "--gain"),
+                "--frequency" => options.height_params.frequency = parse_next(&mut args, "--frequency"),
+                "--amplitude" => options.height_params.amplitude = parse_next(&mut args, "--amplitude"),
+                "--base" => options.height_params.base = parse_next(&mut args, "--base"),
+                other => panic!("Unknown argument: {}", other),
+            }
+        }
+
+        // The level of detail quadtree halves chunks all the way down to single cells
+        if options.size < 2 || !(options.size - 1).is_power_of_two() {
+            panic!("--size must be a power of two plus one");
+        }
+
+        if !(options.grass_spacing >= MIN_GRASS_SPACING) {
+            panic!("--grass-spacing must be at least {}", MIN_GRASS_SPACING);
+        }
+
+        return options;
+    }
+}
@@ -0,0 +1,79 @@
+extern crate rand;
+
+use rand::Rng;
+
+use std::f32::consts::PI;
+
+// Candidates tried around a point before giving up on it
+const ATTEMPTS: usize = 30;
+
+/// Blue noise points in `0..width` by `0..depth`, no two closer than `spacing`,
+/// using Bridson's fast Poisson-disk sampling.
+pub fn sample<R: Rng>(rng: &mut R, width: f32, depth: f32, spacing: f32) -> Vec<(f32, f32)> {
+    debug_assert!(spacing > 0.0, "Poisson-disk spacing must be positive");
+
+    // A cell this size holds at most one point
+    let cell = spacing / 2.0f32.sqrt();
+    let columns = (width / cell).ceil() as usize;
+    let rows = (depth / cell).ceil() as usize;
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+
+    let mut points = Vec::new();
+    let mut active = Vec::new();
+
+    let first = (rng.gen::<f32>() * width, rng.gen::<f32>() * depth);
+    grid[(first.1 / cell) as usize * columns + (first.0 / cell) as usize] = Some(0);
+    points.push(first);
+    active.push(0);
+
+    while !active.is_empty() {
+        let i = rng.gen_range(0, active.len());
+        let (px, pz) = points[active[i]];
+
+        let mut found = false;
+        for _ in 0..ATTEMPTS {
+            // Somewhere in the ring between one and two times the spacing
+            let angle = rng.gen::<f32>() * 2.0 * PI;
+            let radius = spacing * (1.0 + rng.gen::<f32>());
+            let (x, z) = (px + radius * angle.cos(), pz + radius * angle.sin());
+            if x < 0.0 || z < 0.0 || x >= width || z >= depth {
+                continue;
+            }
+
+            let (column, row) = ((x / cell) as usize, (z / cell) as usize);
+            if is_far_enough(&grid, &points, columns, rows, column, row, x, z, spacing) {
+                grid[row * columns + column] = Some(points.len());
+                active.push(points.len());
+                points.push((x, z));
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            active.swap_remove(i);
+        }
+    }
+
+    return points;
+}
+
+fn is_far_enough(grid: &[Option<usize>], points: &[(f32, f32)], columns: usize, rows: usize,
+                 column: usize, row: usize, x: f32, z: f32, spacing: f32) -> bool {
+    // Points closer than the spacing can be at most two cells away
+    let min_row = if row >= 2 { row - 2 } else { 0 };
+    let min_column = if column >= 2 { column - 2 } else { 0 };
+
+    for r in min_row..(row + 3).min(rows) {
+        for c in min_column..(column + 3).min(columns) {
+            if let Some(index) = grid[r * columns + c] {
+                let (ox, oz) = points[index];
+                if (ox - x) * (ox - x) + (oz - z) * (oz - z) < spacing * spacing {
+                    return false;
+                }
+            }
+        }
+    }
+
+    return true;
+}
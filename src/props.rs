@@ -0,0 +1,154 @@
+extern crate rand;
+
+use rand::Rng;
+
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+
+use grass::PlacementRules;
+use heightmap;
+use heightmap::HeightSource;
+use rendering::{MeshVertex, PropAttrs};
+use poisson;
+use support;
+use support::MeshError;
+
+// Keeps the prop placement stream independent from the grass
+const PROP_SEED_SALT: u32 = 0x70726f70;
+
+/// Where the mesh of a prop kind comes from.
+#[derive(Clone, Debug)]
+pub enum MeshSource {
+    /// Compiled into the binary.
+    Builtin(&'static [u8]),
+    /// Path to a Wavefront OBJ file.
+    File(String),
+}
+
+impl MeshSource {
+    /// Reads the mesh, scaled to be one unit across.
+    pub fn load(&self) -> Result<Vec<MeshVertex>, MeshError> {
+        match *self {
+            MeshSource::Builtin(bytes) => support::load_wavefront(bytes),
+            MeshSource::File(ref path) => {
+                let mut bytes = Vec::new();
+                try!(try!(File::open(path)).read_to_end(&mut bytes));
+                support::load_wavefront(&bytes)
+            },
+        }
+    }
+}
+
+/// A kind of prop, like a flower, rock or stump, and how it is spread over the terrain.
+///
+/// Meshes are scaled to be one unit across when loaded, so the scales are in world units.
+#[derive(Clone, Debug)]
+pub struct PropKind {
+    pub mesh: MeshSource,
+    /// Smallest distance between two props of this kind.
+    pub spacing: f32,
+    /// Instance sizes are spread evenly between these.
+    pub min_scale: f32,
+    pub max_scale: f32,
+    pub tint: [f32; 3],
+}
+
+/// Parses `path.obj,spacing,scale`.
+impl FromStr for PropKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let parts: Vec<&str> = s.split(',').collect();
+        if parts.len() != 3 {
+            return Err(());
+        }
+
+        let spacing: f32 = try!(parts[1].trim().parse().map_err(|_| ()));
+        let scale: f32 = try!(parts[2].trim().parse().map_err(|_| ()));
+        if !(spacing > 0.0) {
+            return Err(());
+        }
+
+        Ok(PropKind {
+            mesh: MeshSource::File(parts[0].to_string()),
+            spacing: spacing,
+            min_scale: 0.8 * scale,
+            max_scale: 1.2 * scale,
+            tint: [0.6, 0.6, 0.6],
+        })
+    }
+}
+
+/// The teapot test mesh, for trying out props without any meshes of your own.
+pub fn teapots() -> Vec<PropKind> {
+    vec![
+        PropKind {
+            mesh: MeshSource::Builtin(include_bytes!("support/teapot.obj")),
+            spacing: 12.0,
+            min_scale: 0.4,
+            max_scale: 0.8,
+            tint: [0.8, 0.3, 0.3],
+        },
+    ]
+}
+
+/// The props of one kind in a chunk, drawn or culled together.
+pub struct PropInstances {
+    /// Index into the prop kinds.
+    pub kind: usize,
+    pub instances: Vec<PropAttrs>,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// Props over a grid of `size` by `size` vertices starting at (`origin_x`, `origin_z`),
+/// deterministic for a given seed. Props follow the same placement rules as the grass.
+pub fn scatter_props(heights: &HeightSource, kinds: &[PropKind], rules: &PlacementRules, size: u32, origin_x: f32, origin_z: f32, seed: u32) -> Vec<PropInstances> {
+    let extent = (size - 1) as f32;
+    let mut props = Vec::new();
+
+    for (kind_index, kind) in kinds.iter().enumerate() {
+        let mut rng = heightmap::seeded_rng(seed ^ PROP_SEED_SALT ^ (kind_index as u32).wrapping_mul(2654435761));
+        let margin = kind.spacing / 2.0;
+        let mut instances = Vec::new();
+
+        for (x, z) in poisson::sample(&mut rng, extent, extent, kind.spacing) {
+            // Always draw both, so the rest of the stream does not depend on the rules
+            let scale = kind.min_scale + rng.gen::<f32>() * (kind.max_scale - kind.min_scale);
+            let rotation = rng.gen::<f32>() * 2.0 * PI;
+
+            // Same as the grass, stay clear of the edges to keep the spacing towards the neighbours
+            if x < margin || z < margin || x > extent - margin || z > extent - margin {
+                continue;
+            }
+
+            let px = origin_x + x;
+            let pz = origin_z + z;
+            let height = heights.get_height(px, pz);
+            if !rules.allows(heights, px, pz, height) {
+                continue;
+            }
+
+            instances.push(PropAttrs { offset: [px, height, pz], scale: scale, rotation: rotation, tint: kind.tint });
+        }
+
+        if instances.is_empty() {
+            continue;
+        }
+
+        let mut min = instances[0].offset;
+        let mut max = instances[0].offset;
+        for instance in instances.iter() {
+            for i in 0..3 {
+                min[i] = min[i].min(instance.offset[i] - instance.scale);
+                max[i] = max[i].max(instance.offset[i] + instance.scale);
+            }
+        }
+
+        props.push(PropInstances { kind: kind_index, instances: instances, min: min, max: max });
+    }
+
+    return props;
+}
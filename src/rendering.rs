@@ -1,5 +1,7 @@
 extern crate glium;
 
+use cgmath::{FixedArray, Matrix, Matrix4};
+
 
 #[derive(Copy, Clone)]
 pub struct Vertex {
@@ -7,6 +9,20 @@ pub struct Vertex {
     pub tex_coords: [f32; 2],
 }
 
+#[derive(Copy, Clone)]
+pub struct TerrainVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
+#[derive(Copy, Clone)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
 #[derive(Copy, Clone)]
 pub struct PosOnlyVertex {
     pub position: [f32; 3],
@@ -16,11 +32,27 @@ pub struct PosOnlyVertex {
 pub struct GrassAttrs {
     pub offset: [f32; 3],
     pub rand_factor: f32,
+    /// Index into the species table, a float since that is what the shaders read.
+    pub species: f32,
+    /// Normal of the terrain under the blade, blended into the lighting of the blade.
+    pub ground_normal: [f32; 3],
+}
+
+#[derive(Copy, Clone)]
+pub struct PropAttrs {
+    pub offset: [f32; 3],
+    pub scale: f32,
+    /// Around the vertical axis, in radians.
+    pub rotation: f32,
+    pub tint: [f32; 3],
 }
 
 implement_vertex!(Vertex, position, tex_coords);
+implement_vertex!(TerrainVertex, position, normal, tex_coords);
+implement_vertex!(MeshVertex, position, normal, tex_coords);
 implement_vertex!(PosOnlyVertex, position);
-implement_vertex!(GrassAttrs, offset, rand_factor);
+implement_vertex!(GrassAttrs, offset, rand_factor, species, ground_normal);
+implement_vertex!(PropAttrs, offset, scale, rotation, tint);
 
 
 pub struct RenderData<V: glium::vertex::Vertex + Send + Copy + 'static> {
@@ -44,3 +76,31 @@ impl<V: glium::vertex::Vertex + Send + Copy + 'static> RenderData<V> {
         &self.indices
     }
 }
+
+/// A quad covering the whole screen, for the passes that shade every pixel.
+pub fn fullscreen_quad<F: glium::backend::Facade>(display: &F) -> RenderData<Vertex> {
+    RenderData::new(display,
+        vec![
+            Vertex { position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 0.0] },
+            Vertex { position: [1.0, -1.0, 0.0], tex_coords: [1.0, 0.0] },
+            Vertex { position: [1.0, 1.0, 0.0], tex_coords: [1.0, 1.0] },
+            Vertex { position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 1.0] },
+        ],
+        glium::index::TrianglesList(vec![0u16, 1, 2, 0, 2, 3])
+    )
+}
+
+/// Takes the corners of the `fullscreen_quad` at the far plane to world space directions
+/// from the eye. Passed to the fullscreen shaders as `view_rays`.
+pub fn view_ray_matrix(persp_matrix: [[f32; 4]; 4], view_matrix: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    // Only the rotation of the camera matters for a direction
+    let mut rotation = view_matrix;
+    for i in 0..3 {
+        rotation[i][3] = 0.0;
+        rotation[3][i] = 0.0;
+    }
+    rotation[3][3] = 1.0;
+
+    let projection = Matrix4::from_fixed(persp_matrix).mul_m(&Matrix4::from_fixed(rotation));
+    return projection.invert().unwrap().into_fixed();
+}
@@ -0,0 +1,54 @@
+#version 410
+
+uniform sampler2D albedo_texture;
+uniform sampler2D normal_texture;
+uniform sampler2D depth_texture;
+uniform sampler2D material_texture;
+
+uniform vec3 eye_position;
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
+uniform float sun_intensity;
+uniform vec3 sky_light;
+uniform vec3 ground_light;
+uniform float translucency;
+uniform float translucency_power;
+
+// deferred::Material values lit differently from the rest
+uniform int material_unlit;
+uniform int material_grass;
+
+in VertexData {
+    vec3 ray;
+} v_in;
+
+out vec4 output1;
+
+void main() {
+    // The G-buffer is as large as the screen, so there is nothing to filter
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    vec3 albedo = texelFetch(albedo_texture, texel, 0).xyz;
+    int material = int(texelFetch(material_texture, texel, 0).x + 0.5);
+
+    if(material == material_unlit) {
+        output1 = vec4(albedo, 1.0);
+        return;
+    }
+
+    vec3 normal = normalize(texelFetch(normal_texture, texel, 0).xyz);
+    vec3 position = eye_position + normalize(v_in.ray) * texelFetch(depth_texture, texel, 0).x;
+    vec3 to_eye = normalize(eye_position - position);
+
+    vec3 ambient = mix(ground_light, sky_light, 0.5 + 0.5 * normal.y);
+    float diffuse = max(dot(normal, sun_direction), 0.0);
+
+    // Light through the back of the blade, brightest when looking towards the sun
+    float backlight = 0.0;
+    if(material == material_grass) {
+        backlight = translucency * (0.5 * max(dot(-normal, sun_direction), 0.0) +
+                                    pow(max(dot(-to_eye, sun_direction), 0.0), translucency_power));
+    }
+
+    vec3 light = ambient + sun_intensity * sun_color * (diffuse + backlight);
+    output1 = vec4(light * albedo, 1.0);
+}
@@ -1,25 +1,53 @@
 #version 410
 
-uniform sampler2D grass_texture_unit;
+uniform sampler2DArray grass_texture_unit;
 uniform sampler2D mask_texture_unit;
 
+uniform vec3 eye_position;
+
 in VertexData {
     vec2 tex_coord;
     float rand_factor;
+    vec3 tint;
+    float layer;
+    vec3 position;
+    vec3 normal;
+    vec3 ground_normal;
 } v_in;
 
-out vec4 output1;
+out vec4 albedo_output;
+out vec4 normal_output;
+out float depth_output;
+out float material_output;
+
+// deferred::Material::Grass
+const float MATERIAL_GRASS = 2.0;
 
 
 void main() {
-    vec4 grass_color = texture(grass_texture_unit, v_in.tex_coord);
+    vec4 grass_color = texture(grass_texture_unit, vec3(v_in.tex_coord, v_in.layer));
+    grass_color.xyz *= v_in.tint;
 
     float blend = 255 * texture(mask_texture_unit, v_in.tex_coord).x;
     float alpha = (1 - blend) * (grass_color.a - 0.5) + 0.5;
 
     vec3 orange_tint = 0.5 * v_in.rand_factor * vec3(0.8, 0.5, 0.0);
-    vec3 darker_color = clamp(v_in.tex_coord.y, 0.1, 1.0) * grass_color.xyz;
+    vec3 albedo = mix(grass_color.xyz, orange_tint, v_in.rand_factor / 2.0);
 
     if(alpha < 0.3) discard;
-    output1 = vec4(mix(darker_color, orange_tint, v_in.rand_factor / 2.0), alpha);
+
+    // Blades are two sided, light the side facing the camera. Leaning the normal towards
+    // the ground keeps the blades from flickering as they turn
+    vec3 to_eye = normalize(eye_position - v_in.position);
+    vec3 normal = normalize(v_in.normal);
+    if(dot(normal, to_eye) < 0.0) normal = -normal;
+    normal = normalize(mix(normal, normalize(v_in.ground_normal), 0.5));
+
+    // The base of the blade is shadowed by the blades around it
+    float occlusion = clamp(v_in.tex_coord.y, 0.1, 1.0);
+
+    albedo_output = vec4(occlusion * albedo, 1.0);
+    normal_output = vec4(normal, 0.0);
+    depth_output = distance(v_in.position, eye_position);
+    material_output = MATERIAL_GRASS;
 }
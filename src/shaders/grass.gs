@@ -3,45 +3,123 @@
 layout(points) in;
 layout(triangle_strip, max_vertices = 32) out;
 
-uniform vec3 windforce;
+// (x, z) wind force over a square around the camera
+uniform sampler2D wind_field;
+uniform vec2 wind_origin;
+uniform float wind_extent;
+
+// (x, z) push away from whoever walked through the grass, over a square around the camera
+uniform sampler2D trample_map;
+uniform vec2 trample_origin;
+uniform float trample_extent;
+
 uniform mat4 persp_matrix;
 uniform mat4 view_matrix;
 
+// One row per species, see grass::species_texels
+uniform sampler2D species_data;
+
+uniform vec3 eye_position;
+uniform int grass_max_segments;
+uniform int grass_min_segments;
+uniform float grass_segment_distance;
+uniform float grass_card_distance;
+uniform float grass_thin_start;
+uniform float grass_max_distance;
+
 in VertexData {
     float rand_factor;
+    float species;
+    vec3 ground_normal;
 } v_in[1];
 
 out VertexData {
     vec2 tex_coord;
     float rand_factor;
+    vec3 tint;
+    float layer;
+    vec3 position;
+    vec3 normal;
+    vec3 ground_normal;
 } v_out;
 
+// The same for every vertex of a blade, set once in main
+mat4 totalMatrix;
+vec3 tint;
+float layer;
+
+// Outputs are undefined after every EmitVertex, so all of them are set each time
+void emit(vec4 position, vec2 tex_coord, vec3 normal) {
+    v_out.tex_coord = tex_coord;
+    v_out.rand_factor = v_in[0].rand_factor;
+    v_out.tint = tint;
+    v_out.layer = layer;
+    v_out.position = position.xyz;
+    v_out.normal = normal;
+    v_out.ground_normal = v_in[0].ground_normal;
+    gl_Position = totalMatrix * position;
+    EmitVertex();
+}
+
 
 float rand(vec2 co) {
     return fract(sin(dot(co.xy, vec2(12.9898, 78.233))) * 43758.5453);
 }
 
 void main(){
-    mat4 totalMatrix = persp_matrix * view_matrix;
+    totalMatrix = persp_matrix * view_matrix;
 
     vec4 seedPos = gl_in[0].gl_Position;
     float personal = rand(seedPos.xz);
     float personal2 = v_in[0].rand_factor;
-    seedPos.x += personal - 0.5;
-    seedPos.z += v_in[0].rand_factor - 0.5;
 
     vec4 up = vec4(fract(personal / 10), 1.0, -fract(personal2 / 10), 0.0);
 
-    float height = 1.0;
-    float width = 0.02;
+    int species = int(v_in[0].species + 0.5);
+    vec4 shape = texelFetch(species_data, ivec2(0, species), 0);
+    vec4 look = texelFetch(species_data, ivec2(1, species), 0);
+    tint = look.xyz;
+    layer = look.w;
+
+    float height = mix(shape.x, shape.y, personal2);
+    float width = shape.z;
+    float stiffness = shape.w;
     vec4 right = vec4(normalize(vec3(personal, 0.0, v_in[0].rand_factor)), 0.0);
     vec4 left = vec4(cross(up.xyz, right.xyz), 0);
 
-    vec4 windVec = vec4(0.8 * windforce, 0.0);
+    vec2 wind = texture(wind_field, (seedPos.xz - wind_origin) / wind_extent).xy;
+    vec4 windVec = vec4(0.8 * (1.0 - stiffness) * vec3(wind.x, 0.0, wind.y), 0.0);
+
+    // Trampled blades are pushed over, which also pulls them down below
+    vec2 trample_coord = (seedPos.xz - trample_origin) / trample_extent;
+    if(all(greaterThanEqual(trample_coord, vec2(0.0))) && all(lessThanEqual(trample_coord, vec2(1.0)))) {
+        vec2 push = texture(trample_map, trample_coord).xy;
+        windVec += vec4(1.5 * height * push.x, 0.0, 1.5 * height * push.y, 0.0);
+    }
     vec4 Ydisplacement = 0.5 * length(windVec) * up;
 
+    float dist = distance(seedPos.xyz, eye_position);
+
+    // Far away blades thin out, the ones with the lowest personal value go first
+    float thinning = clamp((dist - grass_thin_start) / (grass_max_distance - grass_thin_start), 0.0, 1.0);
+    if(dist > grass_max_distance || personal < thinning) return;
+
+    // Beyond the card distance a single wide quad stands in for the blade
+    if(dist > grass_card_distance) {
+        float card_width = 10.0 * width;
+        vec4 top = seedPos + up * height - Ydisplacement + windVec;
+
+        emit(seedPos - right * card_width, vec2(0.0, 0.0), left.xyz);
+        emit(seedPos + right * card_width, vec2(1.0, 0.0), left.xyz);
+        emit(top - right * card_width, vec2(0.0, 1.0), left.xyz);
+        emit(top + right * card_width, vec2(1.0, 1.0), left.xyz);
+        EndPrimitive();
+        return;
+    }
+
     // Side one
-    int lod = 5;
+    float detail = clamp(dist / grass_segment_distance, 0.0, 1.0);
+    int lod = int(mix(float(grass_max_segments), float(grass_min_segments), detail) + 0.5);
     for(int i = 0; i <= lod; i++) {
         float progress = (i/float(lod));
         float next = (i+1/float(lod));
@@ -52,16 +130,10 @@ void main(){
         // Middle point
         vec4 firstSide = (seedPos + heightOffset + windOffset);
 
-        v_out.tex_coord = vec2(0.5, progress);
-        v_out.rand_factor = v_in[0].rand_factor;
-        gl_Position = totalMatrix * firstSide;
-        EmitVertex();
+        emit(firstSide, vec2(0.5, progress), left.xyz);
 
         // Right point (first side + width)
-        gl_Position = totalMatrix * (firstSide + right * width);
-        v_out.rand_factor = v_in[0].rand_factor;
-        v_out.tex_coord = vec2(1.0, progress);
-        EmitVertex();
+        emit(firstSide + right * width, vec2(1.0, progress), left.xyz);
     }
     EndPrimitive();
 
@@ -75,16 +147,10 @@ void main(){
         // Middle point
         vec4 firstSide = (seedPos + heightOffset + windOffset);
 
-        v_out.tex_coord = vec2(0.5, progress);
-        v_out.rand_factor = v_in[0].rand_factor;
-        gl_Position = totalMatrix * firstSide;
-        EmitVertex();
+        emit(firstSide, vec2(0.5, progress), right.xyz);
 
         // Right point (first side - width)
-        gl_Position = totalMatrix * (firstSide - left * width);
-        v_out.tex_coord = vec2(0.0, progress);
-        v_out.rand_factor = v_in[0].rand_factor;
-        EmitVertex();
+        emit(firstSide - left * width, vec2(0.0, progress), right.xyz);
     }
     EndPrimitive();
 }
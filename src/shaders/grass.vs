@@ -3,12 +3,18 @@
 in vec3 position;
 in vec3 offset;
 in float rand_factor;
+in float species;
+in vec3 ground_normal;
 
 out VertexData {
     float rand_factor;
+    float species;
+    vec3 ground_normal;
 } v;
 
 void main() {
     v.rand_factor = rand_factor;
+    v.species = species;
+    v.ground_normal = ground_normal;
     gl_Position = vec4(position + offset, 1.0);
 }
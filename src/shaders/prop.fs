@@ -0,0 +1,24 @@
+#version 410
+
+uniform vec3 eye_position;
+
+in VertexData {
+    vec3 position;
+    vec3 normal;
+    vec3 tint;
+} v_in;
+
+out vec4 albedo_output;
+out vec4 normal_output;
+out float depth_output;
+out float material_output;
+
+// deferred::Material::Prop
+const float MATERIAL_PROP = 3.0;
+
+void main() {
+    albedo_output = vec4(v_in.tint, 1.0);
+    normal_output = vec4(normalize(v_in.normal), 0.0);
+    depth_output = distance(v_in.position, eye_position);
+    material_output = MATERIAL_PROP;
+}
@@ -0,0 +1,33 @@
+#version 410
+
+uniform mat4 persp_matrix;
+uniform mat4 view_matrix;
+
+in vec3 position;
+in vec3 normal;
+in vec2 tex_coords;
+
+in vec3 offset;
+in float scale;
+in float rotation;
+in vec3 tint;
+
+out VertexData {
+    vec3 position;
+    vec3 normal;
+    vec3 tint;
+} v_out;
+
+void main() {
+    // Rotation around the vertical axis
+    mat3 rotate = mat3(cos(rotation), 0.0, -sin(rotation),
+                       0.0, 1.0, 0.0,
+                       sin(rotation), 0.0, cos(rotation));
+
+    vec3 world_position = rotate * position * scale + offset;
+
+    v_out.position = world_position;
+    v_out.normal = rotate * normal;
+    v_out.tint = tint;
+    gl_Position = persp_matrix * view_matrix * vec4(world_position, 1.0);
+}
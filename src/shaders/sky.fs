@@ -0,0 +1,59 @@
+#version 410
+
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
+
+// Preetham sky, one channel each for luminance Y and chromaticity x and y
+uniform vec3 perez_a;
+uniform vec3 perez_b;
+uniform vec3 perez_c;
+uniform vec3 perez_d;
+uniform vec3 perez_e;
+uniform vec3 perez_zenith;
+uniform float sky_exposure;
+
+in VertexData {
+    vec3 ray;
+} v_in;
+
+// Every G-buffer target must be written, even though unlit texels only use albedo and material
+out vec4 albedo_output;
+out vec4 normal_output;
+out float depth_output;
+out float material_output;
+
+// deferred::Material::Unlit
+const float MATERIAL_UNLIT = 0.0;
+
+vec3 perez(float theta, float gamma) {
+    return (1.0 + perez_a * exp(perez_b / cos(theta))) *
+           (1.0 + perez_c * exp(perez_d * gamma) + perez_e * cos(gamma) * cos(gamma));
+}
+
+void main() {
+    vec3 ray = normalize(v_in.ray);
+
+    // Below the horizon the model falls apart, keep the colour of the horizon there
+    float theta = acos(max(ray.y, 0.001));
+    float gamma = acos(clamp(dot(ray, sun_direction), -1.0, 1.0));
+    vec3 Yxy = perez_zenith * perez(theta, gamma);
+
+    vec3 XYZ = vec3(Yxy.y / Yxy.z * Yxy.x, Yxy.x, (1.0 - Yxy.y - Yxy.z) / Yxy.z * Yxy.x);
+    vec3 rgb = mat3(3.2406, -0.9689, 0.0557,
+                    -1.5372, 1.8758, -0.2040,
+                    -0.4986, 0.0415, 1.0570) * XYZ;
+
+    // The sky darkens into the night as the sun sinks below the horizon
+    float twilight = smoothstep(-0.1, 0.05, sun_direction.y);
+    vec3 color = twilight * (vec3(1.0) - exp(-sky_exposure * max(rgb, vec3(0.0))));
+
+    // The sun disc itself, about half a degree across
+    if(dot(ray, sun_direction) > 0.99996) {
+        color += sun_color;
+    }
+
+    albedo_output = vec4(color, 1.0);
+    normal_output = vec4(0.0);
+    depth_output = 0.0;
+    material_output = MATERIAL_UNLIT;
+}
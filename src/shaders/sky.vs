@@ -0,0 +1,17 @@
+#version 410
+
+// From rendering::view_ray_matrix
+uniform mat4 view_rays;
+
+in vec3 position;
+in vec2 tex_coords;
+
+out VertexData {
+    vec3 ray;
+} v_out;
+
+void main() {
+    // Only the rotation of the camera matters for the sky
+    v_out.ray = (view_rays * vec4(position.xy, 1.0, 1.0)).xyz;
+    gl_Position = vec4(position.xy, 1.0, 1.0);
+}
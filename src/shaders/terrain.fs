@@ -1,14 +1,28 @@
 #version 410
 
 uniform sampler2D texture_unit;
+uniform vec3 eye_position;
 
 in VertexData {
+    vec3 position;
+    vec3 normal;
     vec2 tex_coords;
 } v_in;
 
-out vec4 output1;
+out vec4 albedo_output;
+out vec4 normal_output;
+out float depth_output;
+out float material_output;
+
+// The grass texture doubles as the ground, darkened to read as soil between the blades
+const float ground_albedo = 0.3;
+
+// deferred::Material::Terrain
+const float MATERIAL_TERRAIN = 1.0;
 
 void main() {
-    vec3 texture_color = texture(texture_unit, v_in.tex_coords).xyz;
-    output1 = 0.2 * vec4(texture_color, 1.0);
+    albedo_output = vec4(ground_albedo * texture(texture_unit, v_in.tex_coords).xyz, 1.0);
+    normal_output = vec4(normalize(v_in.normal), 0.0);
+    depth_output = distance(v_in.position, eye_position);
+    material_output = MATERIAL_TERRAIN;
 }
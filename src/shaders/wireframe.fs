@@ -0,0 +1,17 @@
+#version 410
+
+// Every G-buffer target must be written, even though unlit texels only use albedo and material
+out vec4 albedo_output;
+out vec4 normal_output;
+out float depth_output;
+out float material_output;
+
+// deferred::Material::Unlit
+const float MATERIAL_UNLIT = 0.0;
+
+void main() {
+    albedo_output = vec4(0.1, 0.9, 0.2, 1.0);
+    normal_output = vec4(0.0);
+    depth_output = 0.0;
+    material_output = MATERIAL_UNLIT;
+}
@@ -0,0 +1,12 @@
+#version 410
+
+uniform mat4 persp_matrix;
+uniform mat4 view_matrix;
+
+in vec3 position;
+in vec3 normal;
+
+void main() {
+    // Lift the lines off the surface so they win the depth test against it
+    gl_Position = persp_matrix * view_matrix * vec4(position + 0.02 * normal, 1.0);
+}
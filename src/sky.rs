@@ -0,0 +1,188 @@
+use glium;
+use glutin;
+
+use std::f32::consts::PI;
+
+use rendering;
+use rendering::{RenderData, Vertex};
+
+/// Where and when the meadow is, and how hazy the air is.
+#[derive(Copy, Clone, Debug)]
+pub struct SkyParams {
+    /// Hours since midnight, local solar time.
+    pub time_of_day: f32,
+    /// Degrees north of the equator, negative for the southern hemisphere.
+    pub latitude: f32,
+    /// Days since the start of the year.
+    pub day_of_year: u32,
+    /// Haziness of the air, from 2 for a clear sky to around 10 for a hazy one.
+    pub turbidity: f32,
+    /// In-game hours passing per real second.
+    pub time_speed: f32,
+    /// Scales the sky radiance before it is tone mapped.
+    pub exposure: f32,
+}
+
+impl Default for SkyParams {
+    fn default() -> Self {
+        SkyParams {
+            time_of_day: 10.0,
+            latitude: 58.0,
+            day_of_year: 172,
+            turbidity: 3.0,
+            time_speed: 0.0,
+            exposure: 0.1,
+        }
+    }
+}
+
+/// Coefficients of the Preetham sky model for luminance Y and chromaticity x and y,
+/// one channel each.
+#[derive(Copy, Clone, Debug)]
+pub struct Perez {
+    pub a: [f32; 3],
+    pub b: [f32; 3],
+    pub c: [f32; 3],
+    pub d: [f32; 3],
+    pub e: [f32; 3],
+    /// Zenith values divided by the Perez function at the zenith, so the shader
+    /// only has to multiply.
+    pub zenith: [f32; 3],
+}
+
+fn perez(a: f32, b: f32, c: f32, d: f32, e: f32, theta: f32, gamma: f32) -> f32 {
+    (1.0 + a * (b / theta.cos()).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+/// The sun and the sky it lights up, drawn behind everything else.
+pub struct Sky {
+    params: SkyParams,
+    quad: RenderData<Vertex>,
+    program: glium::Program,
+}
+
+impl Sky {
+    pub fn new<F: glium::backend::Facade>(display: &F, program: glium::Program, params: SkyParams) -> Self {
+        Sky {
+            params: params,
+            quad: rendering::fullscreen_quad(display),
+            program: program,
+        }
+    }
+
+    /// Lets `dt` real seconds pass.
+    pub fn tick(&mut self, dt: f32) {
+        self.params.time_of_day = (self.params.time_of_day + dt * self.params.time_speed) % 24.0;
+    }
+
+    /// Unit vector towards the sun, x pointing east, y up and z south.
+    pub fn sun_direction(&self) -> [f32; 3] {
+        let p = &self.params;
+        let latitude = p.latitude.to_radians();
+        let declination = -23.44f32.to_radians() * (2.0 * PI / 365.0 * (p.day_of_year as f32 + 10.0)).cos();
+        let hour_angle = (15.0 * (p.time_of_day - 12.0)).to_radians();
+
+        let east = -declination.cos() * hour_angle.sin();
+        let north = declination.sin() * latitude.cos() - declination.cos() * hour_angle.cos() * latitude.sin();
+        let up = declination.sin() * latitude.sin() + declination.cos() * hour_angle.cos() * latitude.cos();
+
+        return [east, up, -north];
+    }
+
+    /// From 0 at night to 1 by day, changing while the sun is close to the horizon.
+    pub fn daylight(&self) -> f32 {
+        // Same as the twilight in sky.fs
+        let t = ((self.sun_direction()[1] + 0.1) / 0.15).max(0.0).min(1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Colour of the sunlight reaching the ground, reddening and fading as the sun sets.
+    pub fn sun_color(&self) -> [f32; 3] {
+        let elevation = self.sun_direction()[1].max(-1.0).min(1.0).asin().to_degrees();
+        if elevation < -2.0 {
+            return [0.0, 0.0, 0.0];
+        }
+
+        // Kasten-Young air mass, then more blue than red scattered away on the way down
+        let zenith = 90.0 - elevation.max(0.0);
+        let air_mass = 1.0 / (zenith.to_radians().cos() + 0.50572 * (96.07995 - zenith).powf(-1.6364));
+        let extinction = [0.05, 0.1, 0.25];
+        let fade = ((elevation + 2.0) / 4.0).max(0.0).min(1.0);
+
+        return [
+            fade * (-extinction[0] * air_mass).exp(),
+            fade * (-extinction[1] * air_mass).exp(),
+            fade * (-extinction[2] * air_mass).exp(),
+        ];
+    }
+
+    /// Preetham sky coefficients for the current sun and turbidity.
+    pub fn perez(&self) -> Perez {
+        let t = self.params.turbidity;
+        // The model only holds with the sun above the horizon, night is handled by the shader
+        let theta_s = self.sun_direction()[1].max(0.01).min(1.0).acos();
+
+        let a = [0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608];
+        let b = [-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092];
+        let c = [-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102];
+        let d = [0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537];
+        let e = [-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let (t2, s, s2, s3) = (t * t, theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t2 * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_yy = t2 * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        let zenith = [zenith_y, zenith_x, zenith_yy];
+        let mut normalised = [0.0; 3];
+        for i in 0..3 {
+            normalised[i] = zenith[i] / perez(a[i], b[i], c[i], d[i], e[i], 0.0, theta_s);
+        }
+
+        Perez { a: a, b: b, c: c, d: d, e: e, zenith: normalised }
+    }
+
+    /// Comma and period move the time of day an hour back and forth.
+    pub fn update(&mut self, event: &glutin::Event) {
+        match event {
+            &glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::Comma)) => {
+                self.params.time_of_day = (self.params.time_of_day + 23.0) % 24.0;
+            },
+            &glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::Period)) => {
+                self.params.time_of_day = (self.params.time_of_day + 1.0) % 24.0;
+            },
+            _ => {},
+        };
+    }
+
+    /// Fills the whole of `frame` with the sky, without touching the depth buffer.
+    pub fn render<S: glium::Surface>(&self, frame: &mut S, persp_matrix: [[f32; 4]; 4], view_matrix: [[f32; 4]; 4]) {
+        let perez = self.perez();
+        let uniforms = uniform! {
+            view_rays: rendering::view_ray_matrix(persp_matrix, view_matrix),
+            sun_direction: self.sun_direction(),
+            sun_color: self.sun_color(),
+            perez_a: perez.a,
+            perez_b: perez.b,
+            perez_c: perez.c,
+            perez_d: perez.d,
+            perez_e: perez.e,
+            perez_zenith: perez.zenith,
+            sky_exposure: self.params.exposure,
+        };
+
+        let params = glium::DrawParameters {
+            depth_test: glium::DepthTest::Overwrite,
+            depth_write: false,
+
+            .. ::std::default::Default::default()
+        };
+
+        frame.draw(self.quad.get_vb(), self.quad.get_ib(), &self.program, &uniforms, &params).unwrap();
+    }
+}
@@ -11,10 +11,14 @@ use self::cgmath::Quaternion;
 use self::cgmath::Rotation3;
 use self::cgmath::{rad, deg};
 use self::cgmath::EuclideanVector;
+use self::cgmath::Matrix;
+
+use super::frustum::Frustum;
 
 pub struct CameraState {
     fov: f32,
     aspect_ratio: f32,
+    screen_height: u32,
     position: Point3<f32>,
     direction: Vector3<f32>,
 
@@ -33,6 +37,7 @@ impl CameraState {
         CameraState {
             fov: 45.0,
             aspect_ratio: width as f32 / height as f32,
+            screen_height: height,
 
             position: Point3{x: 5.0, y: 5.0, z: 5.0},
             direction: Vector3{x: 1.0, y: 0.0, z: 1.0}.normalize(),
@@ -54,12 +59,29 @@ impl CameraState {
         return perspective(deg(self.fov), self.aspect_ratio, znear, zfar);
     }
 
+    pub fn get_position(&self) -> Point3<f32> {
+        self.position
+    }
+
+    pub fn get_frustum(&self) -> Frustum {
+        Frustum::from_matrix(&self.get_perspective().mul_m(&self.get_view()))
+    }
+
+    /// Vertical field of view, in degrees.
+    pub fn get_fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn get_screen_height(&self) -> f32 {
+        self.screen_height as f32
+    }
+
     pub fn get_view(&self) -> Matrix4<f32> {
         let point_to_look_at: Point3<f32> = self.position.add_v(&self.direction);
         return Matrix4::look_at(&self.position, &point_to_look_at, &Vector3::unit_y());
     }
 
-    pub fn update(&mut self, heightmap: &super::super::heightmap::NoiseContext) {
+    pub fn update(&mut self, heightmap: &super::super::heightmap::HeightSource) {
         let speed = 0.1;
 
         self.direction = self.direction.normalize();
@@ -133,6 +155,7 @@ impl CameraState {
 
             &glutin::Event::Resized(width, height) => {
                 self.aspect_ratio = width as f32 / height as f32;
+                self.screen_height = height;
             },
 
             &glutin::Event::MouseMoved((x, y)) => {
@@ -0,0 +1,56 @@
+extern crate cgmath;
+
+use self::cgmath::Matrix4;
+
+/// The six planes bounding what a camera sees, pointing inwards.
+pub struct Frustum {
+    planes: [[f32; 4]; 6],
+}
+
+fn normalize(plane: [f32; 4]) -> [f32; 4] {
+    let length = (plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]).sqrt();
+    [plane[0] / length, plane[1] / length, plane[2] / length, plane[3] / length]
+}
+
+impl Frustum {
+    /// Extracts the planes from a combined projection and view matrix.
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        // cgmath matrices are stored by column, so gather the rows first
+        let rows = [
+            [m.x.x, m.y.x, m.z.x, m.w.x],
+            [m.x.y, m.y.y, m.z.y, m.w.y],
+            [m.x.z, m.y.z, m.z.z, m.w.z],
+            [m.x.w, m.y.w, m.z.w, m.w.w],
+        ];
+
+        let mut planes = [[0.0; 4]; 6];
+        for axis in 0..3 {
+            let mut inside_min = [0.0; 4];
+            let mut inside_max = [0.0; 4];
+            for i in 0..4 {
+                inside_min[i] = rows[3][i] + rows[axis][i];
+                inside_max[i] = rows[3][i] - rows[axis][i];
+            }
+            planes[axis * 2] = normalize(inside_min);
+            planes[axis * 2 + 1] = normalize(inside_max);
+        }
+
+        Frustum { planes: planes }
+    }
+
+    /// Conservative test, boxes near a corner of the frustum may pass without being visible.
+    pub fn intersects_box(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        for plane in self.planes.iter() {
+            // The corner furthest along the plane normal
+            let x = if plane[0] > 0.0 { max[0] } else { min[0] };
+            let y = if plane[1] > 0.0 { max[1] } else { min[1] };
+            let z = if plane[2] > 0.0 { max[2] } else { min[2] };
+
+            if plane[0] * x + plane[1] * y + plane[2] * z + plane[3] < 0.0 {
+                return false;
+            }
+        }
+
+        return true;
+    }
+}
@@ -7,11 +7,18 @@ extern crate genmesh;
 extern crate clock_ticks;
 extern crate obj;
 
+use std::fmt;
 use std::thread;
+use std::io;
+use std::io::BufReader;
 use glium::{self, Display};
 use glium::vertex::VertexBufferAny;
 
+use rendering::MeshVertex;
+
 pub mod camera;
+pub mod frustum;
+pub mod stats;
 
 pub enum Action {
     Stop,
@@ -42,3 +49,85 @@ pub fn start_loop<F>(mut callback: F) where F: FnMut() -> Action {
         thread::sleep_ms(((FIXED_TIME_STAMP - accumulator) / 1000000) as u32);
     }
 }
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(io::Error),
+    /// The file holds no object to take the mesh from.
+    NoObject,
+}
+
+impl From<io::Error> for MeshError {
+    fn from(err: io::Error) -> Self {
+        MeshError::Io(err)
+    }
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MeshError::Io(ref err) => write!(f, "{}", err),
+            MeshError::NoObject => write!(f, "no object in the file"),
+        }
+    }
+}
+
+/// Loads the first object of a Wavefront OBJ file, scaled to be one unit across
+/// and standing on the origin.
+pub fn load_wavefront(data: &[u8]) -> Result<Vec<MeshVertex>, MeshError> {
+    let mut data = BufReader::new(data);
+    let data = obj::Obj::load(&mut data);
+    let object = match data.object_iter().next() {
+        Some(object) => object,
+        None => return Err(MeshError::NoObject),
+    };
+
+    // Quads are split into two triangles
+    let mut corners = Vec::new();
+    for shape in object.group_iter().flat_map(|g| g.indices().iter()) {
+        match shape {
+            &genmesh::Polygon::PolyTri(genmesh::Triangle { x: v1, y: v2, z: v3 }) => {
+                corners.extend([v1, v2, v3].iter().cloned());
+            },
+            &genmesh::Polygon::PolyQuad(genmesh::Quad { x: v1, y: v2, z: v3, w: v4 }) => {
+                corners.extend([v1, v2, v3, v1, v3, v4].iter().cloned());
+            },
+        }
+    }
+
+    let mut vertex_data: Vec<MeshVertex> = corners.iter().map(|v| {
+        let position = data.position()[v.0];
+        let tex_coords = v.1.map(|index| data.texture()[index]).unwrap_or([0.0, 0.0]);
+        let normal = v.2.map(|index| data.normal()[index]).unwrap_or([0.0, 1.0, 0.0]);
+
+        MeshVertex {
+            position: position,
+            normal: normal,
+            tex_coords: tex_coords,
+        }
+    }).collect();
+
+    if vertex_data.is_empty() {
+        return Ok(vertex_data);
+    }
+
+    let mut min = vertex_data[0].position;
+    let mut max = vertex_data[0].position;
+    for vertex in vertex_data.iter() {
+        for i in 0..3 {
+            min[i] = min[i].min(vertex.position[i]);
+            max[i] = max[i].max(vertex.position[i]);
+        }
+    }
+
+    // Center on the vertical axis, with the lowest point at the origin
+    let size = (max[0] - min[0]).max(max[1] - min[1]).max(max[2] - min[2]).max(1e-6);
+    let center = [(min[0] + max[0]) / 2.0, min[1], (min[2] + max[2]) / 2.0];
+    for vertex in vertex_data.iter_mut() {
+        for i in 0..3 {
+            vertex.position[i] = (vertex.position[i] - center[i]) / size;
+        }
+    }
+
+    return Ok(vertex_data);
+}
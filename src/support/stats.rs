@@ -0,0 +1,91 @@
+extern crate clock_ticks;
+
+// Frames averaged over before each report, about one second
+const REPORT_INTERVAL: u64 = 60;
+// Assumed length of the frame before the first one, a 60 Hz frame
+const FIRST_FRAME_TIME: u64 = 16666667;
+
+/// Per-frame timings and counters, printed as averages over `REPORT_INTERVAL` frames.
+pub struct FrameStats {
+    /// Whether the averages are printed, F3 toggles it.
+    pub report: bool,
+    frames: u64,
+    // When the current frame started and how long the previous one took, in nanoseconds
+    frame_start: u64,
+    frame_time: u64,
+    timings: Vec<(&'static str, u64)>,
+    counters: Vec<(&'static str, u64)>,
+}
+
+fn add(entries: &mut Vec<(&'static str, u64)>, name: &'static str, value: u64) {
+    match entries.iter_mut().find(|entry| entry.0 == name) {
+        Some(entry) => {
+            entry.1 += value;
+            return;
+        },
+        None => {},
+    }
+    entries.push((name, value));
+}
+
+impl FrameStats {
+    pub fn new(report: bool) -> Self {
+        FrameStats {
+            report: report,
+            frames: 0,
+            frame_start: clock_ticks::precise_time_ns(),
+            frame_time: FIRST_FRAME_TIME,
+            timings: Vec::new(),
+            counters: Vec::new(),
+        }
+    }
+
+    /// Seconds the previous frame took, from one `end_frame` to the next.
+    pub fn delta(&self) -> f32 {
+        self.frame_time as f32 / 1000000000.0
+    }
+
+    /// Runs `f`, adding the time it took to the `name` timing.
+    pub fn time<T, F: FnOnce() -> T>(&mut self, name: &'static str, f: F) -> T {
+        let start = clock_ticks::precise_time_ns();
+        let result = f();
+        add(&mut self.timings, name, clock_ticks::precise_time_ns() - start);
+        return result;
+    }
+
+    pub fn count(&mut self, name: &'static str, value: u64) {
+        add(&mut self.counters, name, value);
+    }
+
+    /// Call once per frame. Prints, when reporting, and resets the averages every `REPORT_INTERVAL` frames.
+    pub fn end_frame(&mut self) {
+        let now = clock_ticks::precise_time_ns();
+        self.frame_time = now - self.frame_start;
+        self.frame_start = now;
+        add(&mut self.timings, "frame", self.frame_time);
+
+        self.frames += 1;
+        if self.frames < REPORT_INTERVAL {
+            return;
+        }
+
+        if self.report {
+            self.print();
+        }
+
+        self.frames = 0;
+        self.timings.clear();
+        self.counters.clear();
+    }
+
+    fn print(&self) {
+        let mut report = String::new();
+        for &(name, total) in self.timings.iter() {
+            report.push_str(&format!("{}: {:.3} ms  ", name, total as f64 / self.frames as f64 / 1000000.0));
+        }
+        for &(name, total) in self.counters.iter() {
+            report.push_str(&format!("{}: {}  ", name, total / self.frames));
+        }
+        println!("{}", report);
+    }
+}
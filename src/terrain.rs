@@ -1,154 +1,451 @@
 
-extern crate num;
-
-extern crate rand;
-
 use glium;
 use glutin;
 
-use rendering::Vertex;
+use rendering::TerrainVertex;
 use rendering::PosOnlyVertex;
 use rendering::GrassAttrs;
-use rand::Rng;
+use rendering::{MeshVertex, PropAttrs};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use heightmap;
+use heightmap::HeightSource;
+use chunks::{ChunkBuilder, ChunkCoord, ChunkData, ChunkStreamer};
+use grass::{GrassLod, MAX_BLADE_SEGMENTS};
+use support::camera::CameraState;
+use support::frustum::Frustum;
+
+struct Chunk {
+    terrain_vbo: glium::VertexBuffer<TerrainVertex>,
+    grass: Vec<GrassBuffer>,
+    props: Vec<PropBuffer>,
+
+    // Indices are only rebuilt when the view they were selected for changes
+    view: Option<LodView>,
+    indices: Option<LodIndices>,
+    index_buffer: Option<glium::IndexBuffer>,
+    wireframe_buffer: Option<glium::IndexBuffer>,
+}
+
+struct GrassBuffer {
+    attrs: glium::VertexBuffer<GrassAttrs>,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+struct PropBuffer {
+    kind: usize,
+    attrs: glium::VertexBuffer<PropAttrs>,
+    min: [f32; 3],
+    max: [f32; 3],
+}
 
-use heightmap::NoiseContext;
+/// What was drawn in a frame.
+pub struct RenderCounts {
+    pub grass_drawn: usize,
+    pub grass_culled: usize,
+    pub props_drawn: usize,
+}
+
+/// How the terrain triangles are drawn.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WireframeMode {
+    Off,
+    /// Triangle outlines drawn on top of the shaded terrain.
+    Overlay,
+    /// Only the triangle outlines.
+    Only,
+}
 
 pub struct Terrain {
-    terrain_vbo: glium::VertexBuffer<Vertex>,
+    chunks: HashMap<ChunkCoord, Chunk>,
+    streamer: ChunkStreamer,
+
     grass_vbo: glium::VertexBuffer<PosOnlyVertex>,
     grass_indices: glium::index::NoIndices,
-    grass_attrs: glium::VertexBuffer<GrassAttrs>,
+
+    // One mesh per prop kind
+    prop_meshes: Vec<glium::VertexBuffer<MeshVertex>>,
 
     terrain_program: glium::Program,
     grass_program: glium::Program,
-    level: usize,
+    prop_program: glium::Program,
+    wireframe_program: glium::Program,
+    wireframe: WireframeMode,
+    size: u32,
+    lod: LodSettings,
+    view: LodView,
+    grass_lod: GrassLod,
+    eye: [f32; 3],
 }
 
-fn xy_to_index(x: u16, y: u16) -> u16 {
-    return (x * 65 + y) as u16;
+fn xy_to_index(x: u32, y: u32, size: u32) -> u32 {
+    return x * size + y;
 }
 
-fn p_to_index(p: P) -> u16 {
-    return xy_to_index(p.x, p.y);
+fn p_to_index(p: P, size: u32) -> u32 {
+    return xy_to_index(p.x, p.y, size);
 }
 
 #[derive(Copy, Clone)]
 struct P {
-    x: u16,
-    y: u16,
+    x: u32,
+    y: u32,
 }
 
-fn p(x: u16, y: u16) -> P {
+fn p(x: u32, y: u32) -> P {
     P{x: x, y: y}
 }
 
-fn p2(p: P, x: u16, y: u16) -> P {
+fn p2(p: P, x: u32, y: u32) -> P {
     P{x: p.x+x, y: p.y+y}
 }
 
 #[derive(Copy, Clone)]
-struct Square {
+struct Rect {
     top: P,
-    w:  u16,
+    w:  u32,
+    h:  u32,
 }
 
-fn s(p: P, w: u16) -> Square {
-    Square{top: p, w: w}
+fn r(p: P, w: u32, h: u32) -> Rect {
+    Rect{top: p, w: w, h: h}
 }
 
 
-fn full_triangle(a: P, b: P, c: P) -> [u16; 3] {
+fn full_triangle(a: P, b: P, c: P, size: u32) -> [u32; 3] {
     return [
-        p_to_index(a),
-        p_to_index(b),
-        p_to_index(c),
+        p_to_index(a, size),
+        p_to_index(b, size),
+        p_to_index(c, size),
     ];
 }
 
-fn wireframe_triangle(a: P, b: P, c: P) -> [u16; 6] {
+fn wireframe_triangle(a: P, b: P, c: P, size: u32) -> [u32; 6] {
     return [
-        p_to_index(a),
-        p_to_index(b),
+        p_to_index(a, size),
+        p_to_index(b, size),
 
-        p_to_index(b),
-        p_to_index(c),
+        p_to_index(b, size),
+        p_to_index(c, size),
 
-        p_to_index(c),
-        p_to_index(a),
+        p_to_index(c, size),
+        p_to_index(a, size),
     ];
 }
 
-fn subdivide(idx: &mut Vec<u16>, level: usize, sq: Square) {
-    let size = sq.w/2;
-    let tl = sq.top;
-    let tr = p(sq.top.x + sq.w, sq.top.y);
+fn subdivide(idx: &mut Vec<u32>, size: u32, level: usize, rect: Rect) {
+    let tl = rect.top;
+    let tr = p(rect.top.x + rect.w, rect.top.y);
 
-    let br = p(sq.top.x + sq.w, sq.top.y + sq.w);
-    let bl = p(sq.top.x, sq.top.y + sq.w);
+    let br = p(rect.top.x + rect.w, rect.top.y + rect.h);
+    let bl = p(rect.top.x, rect.top.y + rect.h);
 
-    if level > 0 {
-        subdivide(idx, level-1, s(p2(tl,    0,    0), size));
-        subdivide(idx, level-1, s(p2(tl, size,    0), size));
-        subdivide(idx, level-1, s(p2(tl,    0, size), size));
-        subdivide(idx, level-1, s(p2(tl, size, size), size));
+    if level > 0 && (rect.w > 1 || rect.h > 1) {
+        // Sides that are not a power of two split unevenly, and sides of one do not split at all
+        let (w0, h0) = (rect.w - rect.w / 2, rect.h - rect.h / 2);
+        for &(x, w) in [(0, w0), (w0, rect.w / 2)].iter() {
+            for &(y, h) in [(0, h0), (h0, rect.h / 2)].iter() {
+                if w > 0 && h > 0 {
+                    subdivide(idx, size, level-1, r(p2(tl, x, y), w, h));
+                }
+            }
+        }
     } else {
-        idx.push_all(&full_triangle(tl, bl, br));
-        idx.push_all(&full_triangle(tl, tr, br));
+        idx.push_all(&full_triangle(tl, bl, br, size));
+        idx.push_all(&full_triangle(tl, tr, br, size));
     }
 }
 
-static MAX_GRASS_PER_SQUARE: usize = 100;
-const WORLD_SIZE: u16 = 65;
+/// How coarse the terrain may get in the distance.
+///
+/// This is a distance heuristic rather than a true screen-space error metric: the height
+/// the terrain actually deviates by is never measured, every node is assumed to be off by
+/// `NODE_ERROR_PER_CELL` per cell it spans, so a flat plain splits just like a ridge.
+/// In exchange the selection only depends on positions, which keeps neighbouring chunks
+/// and nodes within one level of each other without sharing any data.
+#[derive(Copy, Clone, Debug)]
+pub struct LodSettings {
+    /// Largest error allowed on screen, in pixels, for the assumed node error.
+    pub max_pixel_error: f32,
+}
 
-impl Terrain {
+// Assumed geometric error of a quadtree node, per cell it spans, whatever the terrain under it
+const NODE_ERROR_PER_CELL: f32 = 0.02;
+// Neighbouring leaves never differ by more than one level while this stays above sqrt(2)
+const MIN_DISTANCE_FACTOR: f32 = 2.0;
+
+impl LodSettings {
+    /// Nodes closer to the eye than this many times their size get split,
+    /// so the assumed error of a node stays under `max_pixel_error` on screen.
+    pub fn distance_factor(&self, fov: f32, screen_height: f32) -> f32 {
+        // An error e at distance d covers e * screen_height / (2 * d * tan(fov / 2)) pixels
+        let projection = screen_height / (2.0 * (fov * PI / 360.0).tan());
+        (projection * NODE_ERROR_PER_CELL / self.max_pixel_error).max(MIN_DISTANCE_FACTOR)
+    }
+}
+
+/// What the quadtree selection needs to know about the camera.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LodView {
+    pub eye_x: f32,
+    pub eye_z: f32,
+    pub distance_factor: f32,
+}
+
+/// Whether the node of `w` cells at world position (x, z) should be split.
+/// Only depends on world coordinates, so chunks agree on their shared edges.
+fn should_split(view: &LodView, x: f32, z: f32, w: u32) -> bool {
+    if w <= 1 {
+        return false;
+    }
+
+    let size = w as f32;
+    let dx = (x - view.eye_x).max(view.eye_x - (x + size)).max(0.0);
+    let dz = (z - view.eye_z).max(view.eye_z - (z + size)).max(0.0);
+
+    return (dx * dx + dz * dz).sqrt() < view.distance_factor * size;
+}
+
+/// Triangles of a quadtree selection, along with their outlines for wireframe rendering.
+#[derive(PartialEq)]
+pub struct LodIndices {
+    pub triangles: Vec<u32>,
+    pub lines: Vec<u32>,
+}
 
-    pub fn new<F: glium::backend::Facade>(display: &F, noise_data: &NoiseContext, terrain_program: glium::Program, grass_program: glium::Program) -> Self {
-        let mut vertices = Vec::new();
-        let mut attrs = Vec::new();
+impl LodIndices {
+    fn push(&mut self, a: P, b: P, c: P, size: u32) {
+        self.triangles.push_all(&full_triangle(a, b, c, size));
+        self.lines.push_all(&wireframe_triangle(a, b, c, size));
+    }
+}
 
-        let mut grass_jitter = Vec::new();
-        let mut rng = rand::thread_rng();
-        for _ in 0..MAX_GRASS_PER_SQUARE {
-            let jitter = rng.gen::<(f32, f32, f32)>();
-            grass_jitter.push(jitter);
+fn select_lod(idx: &mut LodIndices, size: u32, origin_x: f32, origin_z: f32, view: &LodView, rect: Rect) {
+    let w = rect.w;
+    let x = origin_x + rect.top.x as f32;
+    let z = origin_z + rect.top.y as f32;
+
+    let half = w / 2;
+    if should_split(view, x, z, w) {
+        for &(dx, dy) in [(0, 0), (half, 0), (0, half), (half, half)].iter() {
+            select_lod(idx, size, origin_x, origin_z, view, r(p2(rect.top, dx, dy), half, half));
         }
+        return;
+    }
 
-        let scale = 1;
-        for x in 0..WORLD_SIZE {
-            for z in 0..WORLD_SIZE {
-                let px = (x * scale) as f32;
-                let pz = (z * scale) as f32;
+    let tl = rect.top;
+    let tr = p2(tl, w, 0);
+    let br = p2(tl, w, w);
+    let bl = p2(tl, 0, w);
+
+    // A split neighbour of the same size has a vertex in the middle of the shared edge
+    let fw = w as f32;
+    let split = [
+        should_split(view, x, z - fw, w),
+        should_split(view, x + fw, z, w),
+        should_split(view, x, z + fw, w),
+        should_split(view, x - fw, z, w),
+    ];
 
-                let height_value = noise_data.get_height(px, pz);
+    if !split.iter().any(|&s| s) {
+        idx.push(tl, bl, br, size);
+        idx.push(tl, tr, br, size);
+        return;
+    }
 
-                vertices.push(Vertex {
-                    position: [ px, height_value, pz],
-                    tex_coords: [px, pz]
-                });
+    // Fan around the center, using the midpoint of every edge next to a finer neighbour
+    let center = p2(tl, half, half);
+    let edges = [
+        (tl, tr, p2(tl, half, 0)),
+        (tr, br, p2(tl, w, half)),
+        (br, bl, p2(tl, half, w)),
+        (bl, tl, p2(tl, 0, half)),
+    ];
+    for (i, &(a, b, mid)) in edges.iter().enumerate() {
+        if split[i] {
+            idx.push(center, a, mid, size);
+            idx.push(center, mid, b, size);
+        } else {
+            idx.push(center, a, b, size);
+        }
+    }
+}
 
-                let grass_per = 100;
-                for &(jitter_x, jitter_z, personal) in grass_jitter.iter().take(grass_per) {
-                    let gx = px + scale as f32 * (jitter_x);
-                    let gz = pz + scale as f32 * (jitter_z);
-                    let grass_height_value = noise_data.get_height(gx, gz);
+/// Triangles for the chunk at (`origin_x`, `origin_z`), finer closer to the eye.
+/// Needs `size - 1` to be a power of two.
+pub fn build_lod_indices(size: u32, origin_x: f32, origin_z: f32, view: &LodView) -> LodIndices {
+    let mut indices = LodIndices { triangles: Vec::new(), lines: Vec::new() };
+    select_lod(&mut indices, size, origin_x, origin_z, view, r(p(0, 0), size-1, size-1));
+    return indices;
+}
 
-                    attrs.push(GrassAttrs { offset: [ gx, grass_height_value, gz ], rand_factor: personal});
-                }
-            }
+/// The selection for `view`, or `None` when it is the same as the `current` one.
+/// Far away chunks often keep their selection when the eye moves, so their upload can be skipped.
+fn reselect_lod(size: u32, origin_x: f32, origin_z: f32, view: &LodView, current: Option<&LodIndices>) -> Option<LodIndices> {
+    let indices = build_lod_indices(size, origin_x, origin_z, view);
+    if current == Some(&indices) {
+        return None;
+    }
+    return Some(indices);
+}
+
+/// Grid of `size` by `size` vertices starting at (`origin_x`, `origin_z`),
+/// with `x` as the outer loop to match `xy_to_index`.
+pub fn build_vertices(heights: &HeightSource, size: u32, origin_x: f32, origin_z: f32) -> Vec<TerrainVertex> {
+    let mut vertices = Vec::with_capacity((size as usize) * (size as usize));
+
+    let scale = 1;
+    for x in 0..size {
+        for z in 0..size {
+            let px = origin_x + (x * scale) as f32;
+            let pz = origin_z + (z * scale) as f32;
+
+            let (height_value, dx, dz) = heights.get_height_with_derivative(px, pz);
+
+            vertices.push(TerrainVertex {
+                position: [ px, height_value, pz],
+                normal: heightmap::normal_from_derivative(dx, dz),
+                tex_coords: [px, pz]
+            });
         }
+    }
+
+    return vertices;
+}
+
+/// Triangle indices for `build_vertices`, at the given subdivision level.
+pub fn build_indices(size: u32, level: usize) -> Vec<u32> {
+    let mut indices = Vec::with_capacity((size as usize) * (size as usize) * 6);
+    subdivide(&mut indices, size, level, r(p(0, 0), size-1, size-1));
+    return indices;
+}
+
+/// The level at which every grid square is its own pair of triangles.
+pub fn max_level(size: u32) -> usize {
+    let mut level = 0;
+    while (1 << level) < size - 1 {
+        level += 1;
+    }
+    return level;
+}
 
+impl Terrain {
+
+    /// Chunks are generated by `builder` and kept loaded up to `view_radius` chunks from the camera.
+    /// `prop_meshes` are indexed like the prop kinds given to `builder`.
+    pub fn new<F: glium::backend::Facade>(display: &F, builder: ChunkBuilder, size: u32, view_radius: i32, prop_meshes: Vec<glium::VertexBuffer<MeshVertex>>,
+                                          terrain_program: glium::Program, grass_program: glium::Program, prop_program: glium::Program, wireframe_program: glium::Program) -> Self {
         let grass_points = glium::VertexBuffer::new(display, vec![
             PosOnlyVertex { position: [ 0.0, 0.0, 0.0] },
         ]);
 
         Terrain {
-            terrain_vbo: glium::VertexBuffer::new(display, vertices),
+            chunks: HashMap::new(),
+            streamer: ChunkStreamer::new(builder, view_radius),
             grass_vbo: grass_points,
             grass_indices: glium::index::NoIndices(glium::index::PrimitiveType::Points),
-            grass_attrs: glium::VertexBuffer::new(display, attrs),
+            prop_meshes: prop_meshes,
             terrain_program: terrain_program,
             grass_program: grass_program,
-            level: 6,
+            prop_program: prop_program,
+            wireframe_program: wireframe_program,
+            wireframe: WireframeMode::Off,
+            size: size,
+            lod: LodSettings { max_pixel_error: 8.0 },
+            view: LodView { eye_x: 0.0, eye_z: 0.0, distance_factor: MIN_DISTANCE_FACTOR },
+            grass_lod: GrassLod::default(),
+            eye: [0.0, 0.0, 0.0],
+        }
+    }
+
+    /// Passed on to `grass.gs` through uniforms.
+    pub fn grass_lod(&self) -> GrassLod {
+        self.grass_lod
+    }
+
+    pub fn set_grass_lod(&mut self, lod: GrassLod) {
+        let mut lod = lod;
+        lod.max_segments = lod.max_segments.max(1).min(MAX_BLADE_SEGMENTS);
+        lod.min_segments = lod.min_segments.max(1).min(lod.max_segments);
+        // Blades have to start thinning out before they are gone
+        if lod.thin_start >= lod.max_distance {
+            lod.thin_start = lod.max_distance / 2.0;
+        }
+        self.grass_lod = lod;
+    }
+
+    /// Uploads finished chunks, asks for missing ones around the camera and drops the far away ones.
+    pub fn stream<F: glium::backend::Facade>(&mut self, display: &F, camera: &CameraState) {
+        let position = camera.get_position();
+        self.eye = [position.x, position.y, position.z];
+        let center = self.streamer.center(position.x, position.z);
+        // Snapping the eye to the cell it is in keeps the selection, and the cached indices,
+        // stable while the camera moves within a cell
+        self.view = LodView {
+            eye_x: position.x.floor() + 0.5,
+            eye_z: position.z.floor() + 0.5,
+            distance_factor: self.lod.distance_factor(camera.get_fov(), camera.get_screen_height()),
+        };
+
+        for data in self.streamer.poll() {
+            // The camera may have moved on while the chunk was being built
+            if self.streamer.is_wanted(data.coord, center) {
+                let ChunkData { coord, vertices, grass, props } = data;
+                self.chunks.insert(coord, Chunk {
+                    terrain_vbo: glium::VertexBuffer::new(display, vertices),
+                    grass: grass.into_iter().map(|bucket| GrassBuffer {
+                        min: bucket.min,
+                        max: bucket.max,
+                        attrs: glium::VertexBuffer::new(display, bucket.blades),
+                    }).collect(),
+                    props: props.into_iter().map(|instances| PropBuffer {
+                        kind: instances.kind,
+                        min: instances.min,
+                        max: instances.max,
+                        attrs: glium::VertexBuffer::new(display, instances.instances),
+                    }).collect(),
+                    view: None,
+                    indices: None,
+                    index_buffer: None,
+                    wireframe_buffer: None,
+                });
+            }
+        }
+
+        // Keep an extra ring around, so walking back and forth over a chunk edge does not thrash
+        let far: Vec<ChunkCoord> = self.chunks.keys()
+            .filter(|coord| coord.distance(center) > self.streamer.view_radius() + 1)
+            .cloned()
+            .collect();
+        for coord in far {
+            self.chunks.remove(&coord);
+        }
+
+        let chunks = &self.chunks;
+        self.streamer.request(center, |coord| chunks.contains_key(&coord));
+
+        self.update_indices(display);
+    }
+
+    fn update_indices<F: glium::backend::Facade>(&mut self, display: &F) {
+        let view = self.view;
+        let size = self.size;
+
+        for (coord, chunk) in self.chunks.iter_mut() {
+            if chunk.view == Some(view) {
+                continue;
+            }
+
+            let (x, z) = coord.origin(size - 1);
+            if let Some(indices) = reselect_lod(size, x, z, &view, chunk.indices.as_ref()) {
+                chunk.index_buffer = Some(glium::IndexBuffer::new(display, glium::index::TrianglesList(indices.triangles.clone())));
+                chunk.wireframe_buffer = Some(glium::IndexBuffer::new(display, glium::index::LinesList(indices.lines.clone())));
+                chunk.indices = Some(indices);
+            }
+            chunk.view = Some(view);
         }
     }
 
@@ -156,27 +453,133 @@ impl Terrain {
         //
         match event {
             &glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::T)) => {
-                if  num::pow(2, self.level + 1) < (WORLD_SIZE - 1) {
-                    self.level += 1;
+                if self.lod.max_pixel_error > 0.5 {
+                    self.lod.max_pixel_error /= 2.0;
                 }
             },
             &glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::G)) => {
-                if self.level > 1 {
-                    self.level -= 1;
+                if self.lod.max_pixel_error < 256.0 {
+                    self.lod.max_pixel_error *= 2.0;
                 }
             },
+            &glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(glutin::VirtualKeyCode::F)) => {
+                self.wireframe = match self.wireframe {
+                    WireframeMode::Off => WireframeMode::Overlay,
+                    WireframeMode::Overlay => WireframeMode::Only,
+                    WireframeMode::Only => WireframeMode::Off,
+                };
+            },
             _ => {},
         };
     }
 
-    pub fn render<F: glium::backend::Facade, S: glium::Surface, U: glium::uniforms::Uniforms>(&self, display: &F, frame: &mut S, uniforms: &U, params: &glium::DrawParameters) {
-        let mut optimised = Vec::with_capacity((WORLD_SIZE * WORLD_SIZE) as usize);
-        subdivide(&mut optimised, self.level, Square{top: p(0, 0), w: WORLD_SIZE-1});
+    /// Grass buckets outside of `frustum`, or further away than the grass is drawn, are skipped,
+    /// and so are props outside of `frustum`.
+    pub fn render<S: glium::Surface, U: glium::uniforms::Uniforms>(&self, frame: &mut S, uniforms: &U, params: &glium::DrawParameters, frustum: &Frustum) -> RenderCounts {
+        let mut counts = RenderCounts { grass_drawn: 0, grass_culled: 0, props_drawn: 0 };
+        let triangles = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
 
-        let indicies = glium::IndexBuffer::new(display, glium::index::TrianglesList(optimised));
+        for chunk in self.chunks.values() {
+            if let Some(ref indicies) = chunk.index_buffer {
+                if self.wireframe != WireframeMode::Only {
+                    frame.draw(&chunk.terrain_vbo, indicies, &self.terrain_program, uniforms, params).unwrap();
+                }
+            }
+            if let Some(ref lines) = chunk.wireframe_buffer {
+                if self.wireframe != WireframeMode::Off {
+                    frame.draw(&chunk.terrain_vbo, lines, &self.wireframe_program, uniforms, params).unwrap();
+                }
+            }
+
+            for bucket in chunk.grass.iter() {
+                if !frustum.intersects_box(bucket.min, bucket.max) || self.distance_to_box(bucket.min, bucket.max) > self.grass_lod.max_distance {
+                    counts.grass_culled += bucket.attrs.len();
+                    continue;
+                }
+
+                frame.draw((&self.grass_vbo, bucket.attrs.per_instance_if_supported().unwrap()), &self.grass_indices, &self.grass_program, uniforms, params).unwrap();
+                counts.grass_drawn += bucket.attrs.len();
+            }
+
+            for props in chunk.props.iter() {
+                if !frustum.intersects_box(props.min, props.max) {
+                    continue;
+                }
+
+                frame.draw((&self.prop_meshes[props.kind], props.attrs.per_instance_if_supported().unwrap()), &triangles, &self.prop_program, uniforms, params).unwrap();
+                counts.props_drawn += props.attrs.len();
+            }
+        }
 
-        frame.draw(&self.terrain_vbo, &indicies, &self.terrain_program, uniforms, params).unwrap();
-        frame.draw((&self.grass_vbo, self.grass_attrs.per_instance_if_supported().unwrap()), &self.grass_indices, &self.grass_program, uniforms, params).unwrap();
+        return counts;
+    }
+
+    fn distance_to_box(&self, min: [f32; 3], max: [f32; 3]) -> f32 {
+        let mut squared = 0.0;
+        for i in 0..3 {
+            let d = (min[i] - self.eye[i]).max(self.eye[i] - max[i]).max(0.0);
+            squared += d * d;
+        }
+        return squared.sqrt();
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{build_lod_indices, reselect_lod, LodIndices, LodSettings, LodView};
+
+    const SIZE: u32 = 65;
+    const FRAMES: usize = 600;
+
+    /// The view of each frame of a walk at camera speed, as `Terrain::stream` snaps it.
+    fn walk() -> Vec<LodView> {
+        let lod = LodSettings { max_pixel_error: 8.0 };
+        let distance_factor = lod.distance_factor(45.0, 1080.0);
+        (0..FRAMES).map(|frame| {
+            let (x, z) = (5.0 + 0.1 * frame as f32, 5.0 + 0.07 * frame as f32);
+            LodView { eye_x: x.floor() + 0.5, eye_z: z.floor() + 0.5, distance_factor: distance_factor }
+        }).collect()
+    }
+
+    /// Origins of the chunks loaded around the start of the walk.
+    fn chunk_origins() -> Vec<(f32, f32)> {
+        let cells = (SIZE - 1) as i32;
+        let mut origins = Vec::new();
+        for z in -2..3 {
+            for x in -2..3 {
+                origins.push(((x * cells) as f32, (z * cells) as f32));
+            }
+        }
+        return origins;
+    }
+
+    /// `Terrain::update_indices` only selects again when the snapped view changes and only
+    /// uploads changed selections, which must still leave every chunk with the selection
+    /// it would get from scratch.
+    #[test]
+    fn lod_cache_skips_work() {
+        let views = walk();
+        let origins = chunk_origins();
+
+        let mut cache: Vec<(Option<LodView>, Option<LodIndices>)> = origins.iter().map(|_| (None, None)).collect();
+        let (mut selections, mut uploads) = (0, 0);
+        for view in views.iter() {
+            for (&(x, z), entry) in origins.iter().zip(cache.iter_mut()) {
+                if entry.0 != Some(*view) {
+                    selections += 1;
+                    if let Some(indices) = reselect_lod(SIZE, x, z, view, entry.1.as_ref()) {
+                        uploads += 1;
+                        entry.1 = Some(indices);
+                    }
+                    entry.0 = Some(*view);
+                }
+
+                assert!(entry.1.as_ref() == Some(&build_lod_indices(SIZE, x, z, view)));
+            }
+        }
+
+        // Selecting and uploading every chunk every frame is what the cache replaced
+        assert!(selections < FRAMES * origins.len());
+        assert!(uploads < selections);
+    }
+}
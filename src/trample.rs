@@ -0,0 +1,120 @@
+// Texels along each side of the trample map
+const MAP_TEXELS: usize = 128;
+// World units along each side of the trample map, only grass close to the camera gets trampled
+const MAP_EXTENT: f32 = 32.0;
+
+/// Something walking through the grass, bending the blades around it away.
+#[derive(Copy, Clone, Debug)]
+pub struct Actor {
+    pub x: f32,
+    pub z: f32,
+    pub radius: f32,
+}
+
+/// Where grass is bent down, kept on the CPU over a square around the camera.
+pub struct TrampleMap {
+    /// Seconds for flattened grass to stand most of the way back up.
+    pub recovery_time: f32,
+    // Texel coordinates of the first texel, so the map only ever moves by whole texels
+    origin: (i32, i32),
+    // (x, z) push per texel, away from whoever stepped there, row by row
+    pushes: Vec<(f32, f32)>,
+}
+
+impl TrampleMap {
+    pub fn new() -> Self {
+        TrampleMap {
+            recovery_time: 5.0,
+            origin: (0, 0),
+            pushes: vec![(0.0, 0.0); MAP_TEXELS * MAP_TEXELS],
+        }
+    }
+
+    /// World position of the first texel.
+    pub fn origin(&self) -> [f32; 2] {
+        let texel = MAP_EXTENT / MAP_TEXELS as f32;
+        [self.origin.0 as f32 * texel, self.origin.1 as f32 * texel]
+    }
+
+    /// World units along each side of the map.
+    pub fn extent(&self) -> f32 {
+        MAP_EXTENT
+    }
+
+    /// Lets the grass recover for `dt` seconds, centers the map on (`center_x`, `center_z`)
+    /// and bends the grass under the actors.
+    pub fn update(&mut self, dt: f32, center_x: f32, center_z: f32, actors: &[Actor]) {
+        // After `recovery_time` about 5% of the push is left
+        let recovery = (-3.0 * dt / self.recovery_time).exp();
+        for push in self.pushes.iter_mut() {
+            push.0 *= recovery;
+            push.1 *= recovery;
+        }
+
+        let texel = MAP_EXTENT / MAP_TEXELS as f32;
+        let half = (MAP_TEXELS / 2) as i32;
+        self.recenter(((center_x / texel).floor() as i32 - half, (center_z / texel).floor() as i32 - half));
+
+        for actor in actors {
+            self.stamp(actor);
+        }
+    }
+
+    /// Rows of the trample texture, (x, z) push per texel.
+    pub fn texels(&self) -> Vec<Vec<(f32, f32)>> {
+        self.pushes.chunks(MAP_TEXELS).map(|row| row.to_vec()).collect()
+    }
+
+    /// Moves the map, keeping what is still covered and starting the rest upright.
+    fn recenter(&mut self, origin: (i32, i32)) {
+        if origin == self.origin {
+            return;
+        }
+
+        let (shift_x, shift_z) = (origin.0 - self.origin.0, origin.1 - self.origin.1);
+        let size = MAP_TEXELS as i32;
+        let mut pushes = vec![(0.0, 0.0); MAP_TEXELS * MAP_TEXELS];
+        for z in 0..size {
+            for x in 0..size {
+                let (old_x, old_z) = (x + shift_x, z + shift_z);
+                if old_x >= 0 && old_z >= 0 && old_x < size && old_z < size {
+                    pushes[(z * size + x) as usize] = self.pushes[(old_z * size + old_x) as usize];
+                }
+            }
+        }
+
+        self.pushes = pushes;
+        self.origin = origin;
+    }
+
+    /// Pushes the grass under `actor` away from its center, keeping any stronger push already there.
+    fn stamp(&mut self, actor: &Actor) {
+        let texel = MAP_EXTENT / MAP_TEXELS as f32;
+        let origin = self.origin();
+        let (origin_x, origin_z) = (origin[0], origin[1]);
+
+        let min_x = (((actor.x - actor.radius - origin_x) / texel).floor() as i32).max(0);
+        let min_z = (((actor.z - actor.radius - origin_z) / texel).floor() as i32).max(0);
+        let max_x = (((actor.x + actor.radius - origin_x) / texel).ceil() as i32).min(MAP_TEXELS as i32 - 1);
+        let max_z = (((actor.z + actor.radius - origin_z) / texel).ceil() as i32).min(MAP_TEXELS as i32 - 1);
+
+        for z in min_z..(max_z + 1) {
+            for x in min_x..(max_x + 1) {
+                let dx = origin_x + (x as f32 + 0.5) * texel - actor.x;
+                let dz = origin_z + (z as f32 + 0.5) * texel - actor.z;
+                let distance = (dx * dx + dz * dz).sqrt();
+                if distance >= actor.radius || distance == 0.0 {
+                    continue;
+                }
+
+                // Strongest right under the actor, fading out towards its edge
+                let strength = 1.0 - distance / actor.radius;
+                let index = z as usize * MAP_TEXELS + x as usize;
+                let old = self.pushes[index];
+                if strength * strength > old.0 * old.0 + old.1 * old.1 {
+                    self.pushes[index] = (strength * dx / distance, strength * dz / distance);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use heightmap::NoiseContext;
+
+// Keeps the gusts independent from the terrain noise
+const WIND_SEED_SALT: u32 = 0x77696e64;
+
+// Texels along each side of the wind field
+const FIELD_TEXELS: usize = 64;
+// World units along each side of the wind field, enough to cover all the drawn grass
+const FIELD_EXTENT: f32 = 128.0;
+
+/// What the wind is like over the whole meadow.
+#[derive(Copy, Clone, Debug)]
+pub struct WindParams {
+    /// Where the wind blows towards, in radians from the x axis.
+    pub direction: f32,
+    /// Average force of the wind.
+    pub strength: f32,
+    /// How much the gusts add to and take from the strength, as a fraction of it.
+    pub gust_strength: f32,
+    /// Gusts per world unit.
+    pub gust_frequency: f32,
+    /// World units the gusts move downwind per second.
+    pub gust_speed: f32,
+    /// How far the local wind direction swings away from `direction`, roughly in radians.
+    pub turbulence: f32,
+}
+
+impl Default for WindParams {
+    fn default() -> Self {
+        WindParams {
+            direction: 0.0,
+            strength: 0.3,
+            gust_strength: 0.8,
+            gust_frequency: 0.05,
+            gust_speed: 6.0,
+            turbulence: 0.5,
+        }
+    }
+}
+
+/// Wind over a square around the camera, for the grass to read from a texture.
+pub struct WindField {
+    noise: NoiseContext,
+    params: WindParams,
+    time: f32,
+    origin: [f32; 2],
+    // (x, z) force per texel, row by row
+    forces: Vec<(f32, f32)>,
+}
+
+impl WindField {
+    pub fn new(params: WindParams, seed: u32) -> Self {
+        let mut field = WindField {
+            noise: NoiseContext::with_seed(64, seed ^ WIND_SEED_SALT),
+            params: params,
+            time: 0.0,
+            origin: [0.0, 0.0],
+            forces: vec![(0.0, 0.0); FIELD_TEXELS * FIELD_TEXELS],
+        };
+        field.fill();
+        return field;
+    }
+
+    /// World position of the first texel.
+    pub fn origin(&self) -> [f32; 2] {
+        self.origin
+    }
+
+    /// World units along each side of the field.
+    pub fn extent(&self) -> f32 {
+        FIELD_EXTENT
+    }
+
+    /// Moves the gusts on by `dt` seconds and centers the field on (`center_x`, `center_z`).
+    pub fn update(&mut self, dt: f32, center_x: f32, center_z: f32) {
+        self.time += dt;
+
+        // Snap to whole texels, so the field does not swim under the grass as the camera moves
+        let texel = FIELD_EXTENT / FIELD_TEXELS as f32;
+        self.origin = [
+            (center_x / texel).floor() * texel - FIELD_EXTENT / 2.0,
+            (center_z / texel).floor() * texel - FIELD_EXTENT / 2.0,
+        ];
+
+        self.fill();
+    }
+
+    /// Wind force at a world position.
+    pub fn sample(&self, x: f32, z: f32) -> (f32, f32) {
+        let p = &self.params;
+        let (dir_x, dir_z) = (p.direction.cos(), p.direction.sin());
+
+        // Gusts are carried along by the wind itself. Noise is roughly within -0.7..0.7,
+        // so it is scaled up to swing the full range
+        let travelled = p.gust_speed * self.time;
+        let gx = (x - dir_x * travelled) * p.gust_frequency;
+        let gz = (z - dir_z * travelled) * p.gust_frequency;
+        let gust = 1.0 + p.gust_strength * 1.4 * self.noise.get(gx, gz);
+
+        // Faster, smaller eddies turning the wind to the sides, taken far from the gusts in the lattice
+        let swirl = self.noise.get(gx * 3.0 + 31.7, gz * 3.0 + 17.3 + 0.3 * self.time);
+        let angle = p.direction + p.turbulence * 1.4 * swirl;
+
+        let force = p.strength * gust.max(0.0);
+        return (force * angle.cos(), force * angle.sin());
+    }
+
+    /// Rows of the wind texture, (x, z) force per texel.
+    pub fn texels(&self) -> Vec<Vec<(f32, f32)>> {
+        self.forces.chunks(FIELD_TEXELS).map(|row| row.to_vec()).collect()
+    }
+
+    fn fill(&mut self) {
+        let texel = FIELD_EXTENT / FIELD_TEXELS as f32;
+        for z in 0..FIELD_TEXELS {
+            for x in 0..FIELD_TEXELS {
+                let force = self.sample(self.origin[0] + (x as f32 + 0.5) * texel, self.origin[1] + (z as f32 + 0.5) * texel);
+                self.forces[z * FIELD_TEXELS + x] = force;
+            }
+        }
+    }
+}